@@ -1,13 +1,18 @@
 #![allow(unused)]
-use clap::{Parser, ValueHint};
+use clap::{Parser, Subcommand, ValueHint};
 use console::{style, Emoji, Style, Term};
 use indicatif::{ProgressBar, ProgressStyle};
 use reqwest::header::{HeaderName, HeaderValue};
 use serde::{Deserialize, Serialize};
-use shirodl::{DownloadFailed, Downloader, ProxyType};
+use shirodl::{
+    input, ArchiveFormat, ConflictPolicy, Decompress, DownloadFailed, Downloader, Extract,
+    HlsVariant, IpFamily, LinkFilter, ManifestFormat, OriginMetadata, PathTemplate, ProxyStrategy,
+    ProxyType, TlsVersion, UrlPattern, VerifyImage,
+};
 use std::fmt::Write;
 use std::io;
-use std::io::Read;
+use std::io::{BufRead, BufReader, Read};
+use std::net::IpAddr;
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
 use std::sync::{mpsc, Arc};
@@ -23,19 +28,136 @@ struct Opts {
     header: Option<String>,
     #[clap(short, long, value_hint=ValueHint::DirPath, help="Download destination folder.")]
     destination: Option<PathBuf>,
+    #[clap(
+        long,
+        value_hint=ValueHint::FilePath,
+        validator(is_archive),
+        help = "Write all files into one archive instead of the destination folder: `.tar`, `.tar.gz`, `.tar.zst` or `.zip`. Entry names are paths relative to the destination."
+    )]
+    archive: Option<PathBuf>,
+    #[clap(
+        long,
+        value_hint=ValueHint::DirPath,
+        help = "Keep downloaded contents once in this folder and hard link them into place. Urls found in it are revalidated instead of downloaded again."
+    )]
+    cache: Option<PathBuf>,
+    #[clap(
+        long,
+        value_hint=ValueHint::FilePath,
+        validator(is_manifest),
+        help = "Write a manifest of the downloaded files once done, can be repeated: `B3SUMS` for `b3sum --check`, `SHA256SUMS` for `sha256sum -c` or a `.jsonl` file with sizes. Relative to the destination."
+    )]
+    manifest: Vec<PathBuf>,
+    #[clap(long, help = "Set the modification time of files from Last-Modified.")]
+    mtime: bool,
+    #[clap(
+        long,
+        possible_values = &["xattr", "sidecar"],
+        help = "Record source url, ETag and content type: `xattr` for extended attributes or `sidecar` for a `.meta.json` file next to each file."
+    )]
+    origin_metadata: Option<String>,
+    #[clap(long, help = "Decompress downloaded `.gz` and `.zst` files.")]
+    decompress: bool,
+    #[clap(
+        long,
+        help = "Extract downloaded zip and tar archives into a folder named after them."
+    )]
+    extract: bool,
+    #[clap(long, help = "Fail images that do not decode.")]
+    verify_images: bool,
+    #[clap(
+        long,
+        help = "Layout of downloaded files, e.g. `{host}/{path_dir}/{basename}.{ext}`. Placeholders: host, path_dir, basename, ext, date, index[:width], query:name, hash8."
+    )]
+    output_template: Option<String>,
+    #[clap(
+        short = 'x',
+        long,
+        help = "Save files as `<host>/<url path>` like `wget -x` when no filename or template is given."
+    )]
+    mirror: bool,
     #[clap(
         long,
         help = "No hash check when file already existed. Not affect hashing when auto rename."
     )]
     no_hash: bool,
-    #[clap(short, long, help = "Timeout in microsecond.")]
+    #[clap(
+        long,
+        possible_values = &["skip", "compare", "overwrite", "keep-both", "keep-newer"],
+        help = "When the file exists: `skip`, `compare`, `overwrite`, `keep-both` or `keep-newer`. Overrides --no-hash."
+    )]
+    on_conflict: Option<String>,
+    #[clap(
+        short,
+        long,
+        help = "Timeout in microsecond. 0 means no total timeout."
+    )]
     timeout: Option<u64>,
+    #[clap(long, help = "Connect timeout in seconds.")]
+    connect_timeout: Option<u64>,
+    #[clap(long, help = "Abort when no data is received for this many seconds.")]
+    idle_timeout: Option<u64>,
+    #[clap(
+        long,
+        requires = "low-speed-time",
+        help = "Abort when slower than this many bytes/sec for `--low-speed-time`."
+    )]
+    low_speed_limit: Option<u64>,
+    #[clap(
+        long,
+        requires = "low-speed-limit",
+        help = "Seconds for `--low-speed-limit`."
+    )]
+    low_speed_time: Option<u64>,
     #[clap(
         short,
         long,
-        help = "Set Proxy, `no` means not set proxy from environment variables."
+        help = "Set Proxy, `no` means not set proxy from environment variables. Multiple proxies are separated by `,`."
     )]
     proxy: Option<String>,
+    #[clap(
+        long,
+        possible_values = &["combined", "round-robin", "random", "sticky"],
+        help = "How tasks are spread over multiple proxies: `combined`, `round-robin`, `random` or `sticky`.",
+        default_value = "round-robin"
+    )]
+    proxy_strategy: String,
+    #[clap(long, value_hint=ValueHint::FilePath, help = "Additional trusted CA bundle in PEM format, can be repeated.")]
+    ca_cert: Vec<PathBuf>,
+    #[clap(long, value_hint=ValueHint::FilePath, requires = "client-key", help = "Client certificate in PEM format for mTLS.")]
+    client_cert: Option<PathBuf>,
+    #[clap(long, value_hint=ValueHint::FilePath, requires = "client-cert", help = "PKCS#8 private key in PEM format for mTLS.")]
+    client_key: Option<PathBuf>,
+    #[clap(
+        long,
+        possible_values = &["1.0", "1.1", "1.2"],
+        help = "Minimum TLS version: `1.0`, `1.1` or `1.2`."
+    )]
+    min_tls: Option<String>,
+    #[clap(long, help = "Accept invalid TLS certificates. Dangerous!")]
+    insecure: bool,
+    #[clap(long, help = "Maximum redirects followed per task.")]
+    max_redirects: Option<usize>,
+    #[clap(long, help = "Refuse redirects to another host.")]
+    same_host_redirects: bool,
+    #[clap(long, help = "Name files after the final url when redirected.")]
+    name_from_final_url: bool,
+    #[clap(
+        long,
+        help = "Resolve host to address, usage: --resolve host:port:addr, can be repeated."
+    )]
+    resolve: Vec<String>,
+    #[clap(
+        short = '4',
+        long,
+        conflicts_with = "ipv6",
+        help = "Only connect over IPv4."
+    )]
+    ipv4: bool,
+    #[clap(short = '6', long, help = "Only connect over IPv6.")]
+    ipv6: bool,
+    #[clap(long, help = "Bind outgoing connections to this local address.")]
+    local_address: Option<IpAddr>,
     #[clap(short, long, help = "Async task count.", default_value = "8")]
     jobs: usize,
     #[clap(
@@ -44,6 +166,98 @@ struct Opts {
         help = "Use json format as input. field: `url`, `filename`, `folder`."
     )]
     json: bool,
+    #[clap(
+        long,
+        conflicts_with = "json",
+        help = "Use aria2 input file format. options: `dir`, `out`, `header`, `checksum`."
+    )]
+    aria2: bool,
+    #[clap(
+        long,
+        conflicts_with_all = &["json", "aria2", "jsonl", "csv", "tsv", "stream", "globbing", "extract-links"],
+        help = "Use a Metalink v4/v3 document as input, files are checked against its sizes and hashes."
+    )]
+    metalink: bool,
+    #[clap(
+        long,
+        conflicts_with_all = &["json", "aria2"],
+        help = "Use JSON Lines as input, one object per line. fields: `url`, `folder`, `filename`, `hash`, `headers`."
+    )]
+    jsonl: bool,
+    #[clap(
+        long,
+        conflicts_with_all = &["json", "aria2", "jsonl", "tsv"],
+        help = "Use CSV as input. columns: `url`, `folder`, `filename`, `hash`, `headers`."
+    )]
+    csv: bool,
+    #[clap(
+        long,
+        conflicts_with_all = &["json", "aria2", "jsonl"],
+        help = "Use tab separated values as input, columns are the same as --csv."
+    )]
+    tsv: bool,
+    #[clap(
+        long,
+        help = "Map task fields to columns of --csv/--tsv or fields of --jsonl, by name or zero-based position, e.g. `url=link,folder=2`."
+    )]
+    columns: Option<String>,
+    #[clap(long, help = "The first row of --csv/--tsv is data, not column names.")]
+    no_header_row: bool,
+    #[clap(
+        long,
+        help = "Start downloading while stdin is still being read, e.g. urls piped from a crawler. Always on for --input files, unless --extract-links."
+    )]
+    stream: bool,
+    #[clap(
+        long,
+        conflicts_with = "stream",
+        help = "Treat inputs as HTML pages, sitemaps or feeds and download the links found in them."
+    )]
+    extract_links: bool,
+    #[clap(
+        long,
+        requires = "extract-links",
+        help = "Only download links with these extensions, e.g. `jpg,png`."
+    )]
+    accept_ext: Option<String>,
+    #[clap(
+        long,
+        requires = "extract-links",
+        help = "Only download links matching this regex."
+    )]
+    accept_regex: Option<String>,
+    #[clap(
+        long,
+        requires = "extract-links",
+        default_value = "0",
+        help = "Follow other links on the same host as pages up to this depth."
+    )]
+    depth: usize,
+    #[clap(
+        long,
+        help = "Treat inputs as HLS playlists and save each as one `.ts` file. Variant: `highest`, `lowest` or a maximum bandwidth.",
+        min_values = 0,
+        max_values = 1,
+        require_equals = true,
+        default_missing_value = "highest"
+    )]
+    hls: Option<String>,
+    #[clap(
+        short,
+        long,
+        help = "Expand curl-style patterns in urls: `[001-100]`, `[a-z:2]`, `{a,b,c}`."
+    )]
+    globbing: bool,
+    #[clap(
+        long,
+        help = "Stop once this many bytes are written to the destination."
+    )]
+    quota: Option<u64>,
+    #[clap(
+        long,
+        help = "Stop before a file would leave less than this many bytes free on the destination."
+    )]
+    min_free_space: Option<u64>,
     #[clap(short, long, help = "Maxium Retry times", default_value = "3")]
     retry: usize,
     #[clap(
@@ -52,6 +266,16 @@ struct Opts {
         help = "Save Unignorable failed tasks to json format file."
     )]
     save_failed: Option<PathBuf>,
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[clap(
+        about = "Check the files of the input tasks in the destination against their sizes and hashes, nothing is downloaded."
+    )]
+    Verify,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -61,11 +285,22 @@ struct DownloadTask {
     pub folder: Option<PathBuf>,
 }
 
+impl DownloadTask {
+    fn into_task(self) -> shirodl::DownloadTask {
+        shirodl::DownloadTask::new(
+            self.url,
+            self.folder.unwrap_or(".".to_string().into()),
+            self.filename,
+        )
+    }
+}
+
 #[derive(Serialize)]
 struct DownloadFailedSerializable {
     pub url: String,
     pub filename: Option<String>,
     pub folder: Option<PathBuf>,
+    pub redirects: Vec<String>,
     pub error: String,
 }
 
@@ -91,80 +326,258 @@ fn main() {
             }
         });
     }
-    // get inputs
-    let inputs = if let Some(input) = opts.input {
+    // get inputs, streamed so large lists need not fit in memory
+    let stream = opts.stream || (opts.input.is_some() && !opts.extract_links);
+    let reader: Box<dyn BufRead + Send> = if let Some(input) = opts.input {
         // read from file
-        let mut buffer = String::new();
-        std::fs::File::open(input)
-            .unwrap()
-            .read_to_string(&mut buffer)
-            .unwrap();
-        buffer
+        Box::new(BufReader::new(std::fs::File::open(input).unwrap()))
     } else {
         // read from stdin
-        let mut buffer = String::new();
-        std::io::stdin().read_to_string(&mut buffer).unwrap();
-        buffer
+        Box::new(BufReader::new(io::stdin()))
     };
-    let tasks = if opts.json {
-        serde_json::from_str(inputs.as_str()).unwrap()
+    let columns = opts
+        .columns
+        .as_deref()
+        .map(|c| input::ColumnMap::parse(c).unwrap())
+        .unwrap_or_default();
+    // malformed records are reported and skipped
+    let skip_illegal = |task: Result<shirodl::DownloadTask, shirodl::Error>| match task {
+        Ok(task) => Some(task),
+        Err(e) => {
+            println!("{}", style(format!("Skipped: {}", e)).red());
+            None
+        }
+    };
+    let tasks: Box<dyn Iterator<Item = shirodl::DownloadTask> + Send> = if opts.aria2 {
+        Box::new(input::parse_aria2(reader).unwrap().into_iter())
+    } else if opts.metalink {
+        let document = io::read_to_string(reader).unwrap();
+        Box::new(input::parse_metalink(&document).unwrap().into_iter())
+    } else if opts.json {
+        Box::new(
+            serde_json::from_reader::<_, Vec<DownloadTask>>(reader)
+                .unwrap()
+                .into_iter()
+                .map(DownloadTask::into_task),
+        )
+    } else if opts.jsonl {
+        Box::new(input::read_jsonl(reader, &columns).filter_map(skip_illegal))
+    } else if opts.csv || opts.tsv {
+        let delimiter = if opts.tsv { b'\t' } else { b',' };
+        Box::new(
+            input::read_csv(reader, delimiter, !opts.no_header_row, &columns)
+                .unwrap()
+                .filter_map(skip_illegal),
+        )
     } else {
-        let inputs = inputs.lines().collect::<Vec<&str>>();
-        // filtering url
-        inputs
-            .iter()
-            .filter(|l| {
-                let url = if let Ok(url) = reqwest::Url::parse(l) {
-                    url
-                } else {
-                    return false;
-                };
-                if url.scheme() == "http" || url.scheme() == "https" {
-                    true
-                } else {
-                    false
-                }
-            })
-            .map(|v| DownloadTask {
-                url: v.to_string(),
-                folder: None,
-                filename: None,
-            })
-            .collect::<Vec<_>>()
+        let globbing = opts.globbing;
+        Box::new(
+            reader
+                .lines()
+                .map(|l| l.unwrap())
+                // filtering url
+                .filter(move |l| {
+                    if globbing {
+                        // patterns are not valid urls before expanding
+                        return ["http://", "https://", "ftp://", "file://"]
+                            .iter()
+                            .any(|scheme| l.starts_with(scheme));
+                    }
+                    let url = if let Ok(url) = reqwest::Url::parse(l) {
+                        url
+                    } else {
+                        return false;
+                    };
+                    matches!(url.scheme(), "http" | "https" | "ftp" | "file" | "data")
+                })
+                .map(|v| {
+                    DownloadTask {
+                        url: v,
+                        folder: None,
+                        filename: None,
+                    }
+                    .into_task()
+                }),
+        )
     };
 
-    if let Some(dest) = opts.destination {
-        let dest = std::env::current_dir().unwrap().join(dest);
-        downloader.set_destination(dest);
-    } else {
-        downloader.set_destination(std::env::current_dir().unwrap());
-    }
+    let destination = match opts.destination {
+        Some(dest) => std::env::current_dir().unwrap().join(dest),
+        None => std::env::current_dir().unwrap(),
+    };
+    downloader.set_destination(destination.clone());
     if let Some(timeout) = opts.timeout {
-        downloader.set_timeout(Duration::from_micros(timeout));
+        if timeout == 0 {
+            downloader.disable_timeout();
+        } else {
+            downloader.set_timeout(Duration::from_micros(timeout));
+        }
+    }
+    if let Some(connect_timeout) = opts.connect_timeout {
+        downloader.set_connect_timeout(Duration::from_secs(connect_timeout));
+    }
+    if let Some(idle_timeout) = opts.idle_timeout {
+        downloader.set_read_idle_timeout(Duration::from_secs(idle_timeout));
+    }
+    if let (Some(limit), Some(time)) = (opts.low_speed_limit, opts.low_speed_time) {
+        downloader.set_low_speed_limit(limit, Duration::from_secs(time));
     }
     downloader.set_hash_check(!opts.no_hash);
+    if let Some(on_conflict) = opts.on_conflict {
+        downloader.set_conflict_policy(match on_conflict.as_str() {
+            "skip" => ConflictPolicy::Skip,
+            "compare" => ConflictPolicy::CompareHash,
+            "overwrite" => ConflictPolicy::Overwrite,
+            "keep-both" => ConflictPolicy::KeepBoth,
+            "keep-newer" => ConflictPolicy::KeepNewer,
+            _ => unreachable!(),
+        });
+    }
     downloader.set_task_count(opts.jobs);
+    downloader.set_quota(opts.quota);
+    downloader.set_min_free_space(opts.min_free_space);
     downloader.set_retries_count(opts.retry);
+    if let Some(max_redirects) = opts.max_redirects {
+        downloader.set_redirect_limit(max_redirects);
+    }
+    downloader.set_redirect_same_host_only(opts.same_host_redirects);
+    downloader.set_filename_from_final_url(opts.name_from_final_url);
 
     if let Some(proxy) = opts.proxy {
         if proxy.to_lowercase() != "no" {
-            println!("Set Proxy {} for all.", proxy);
-            downloader.add_proxy(ProxyType::All, proxy).unwrap();
+            for proxy in proxy.split(',') {
+                println!("Set Proxy {} for all.", proxy);
+                downloader
+                    .add_proxy(ProxyType::All, proxy.to_string())
+                    .unwrap();
+            }
+            downloader.set_proxy_strategy(match opts.proxy_strategy.as_str() {
+                "combined" => ProxyStrategy::Combined,
+                "random" => ProxyStrategy::Random,
+                "sticky" => ProxyStrategy::StickyPerHost,
+                "round-robin" => ProxyStrategy::RoundRobin,
+                _ => unreachable!(),
+            });
         } else {
             downloader.disable_default_proxy();
         }
     }
 
-    tasks.iter().for_each(|v| {
-        // todo: Running path generator
-        downloader.append_task((
-            v.url.clone(),
-            v.folder.clone().unwrap_or(".".to_string().into()),
-            v.filename.clone(),
-        ))
+    for resolve in opts.resolve {
+        downloader.add_resolve_override(&resolve).unwrap();
+    }
+    if opts.ipv4 {
+        downloader.set_ip_family(IpFamily::V4Only);
+    } else if opts.ipv6 {
+        downloader.set_ip_family(IpFamily::V6Only);
+    }
+    if let Some(local_address) = opts.local_address {
+        downloader.set_local_address(local_address);
+    }
+    for ca_cert in opts.ca_cert {
+        downloader.add_root_certificate(ca_cert).unwrap();
+    }
+    if let (Some(cert), Some(key)) = (opts.client_cert, opts.client_key) {
+        downloader.set_client_certificate(cert, key).unwrap();
+    }
+    if let Some(min_tls) = opts.min_tls {
+        downloader.set_min_tls_version(match min_tls.as_str() {
+            "1.0" => TlsVersion::TLS_1_0,
+            "1.1" => TlsVersion::TLS_1_1,
+            "1.2" => TlsVersion::TLS_1_2,
+            _ => unreachable!(),
+        });
+    }
+    if opts.insecure {
+        println!(
+            "{}",
+            style("TLS certificate verification is disabled!").yellow()
+        );
+        downloader.danger_accept_invalid_certs(true);
+    }
+
+    if let (Some(Command::Verify), Some(_)) = (&opts.command, &opts.archive) {
+        println!(
+            "{}",
+            style("Verify checks the destination, files in an archive are not covered.").red()
+        );
+        std::process::exit(2);
+    }
+    if let Some(archive) = opts.archive {
+        let format = ArchiveFormat::from_path(&archive).unwrap();
+        downloader.set_archive(archive, format);
+    }
+    for manifest in opts.manifest {
+        let format = ManifestFormat::from_path(&manifest).unwrap();
+        downloader.add_manifest(destination.join(manifest), format);
+    }
+    downloader.set_mtime_from_server(opts.mtime);
+    if let Some(origin_metadata) = opts.origin_metadata {
+        downloader.set_origin_metadata(Some(match origin_metadata.as_str() {
+            "xattr" => OriginMetadata::Xattr,
+            "sidecar" => OriginMetadata::Sidecar,
+            _ => unreachable!(),
+        }));
+    }
+    if opts.decompress {
+        downloader.add_hook(Decompress::default());
+    }
+    if opts.extract {
+        downloader.add_hook(Extract::default());
+    }
+    if opts.verify_images {
+        downloader.add_hook(VerifyImage);
+    }
+    if let Some(cache) = opts.cache {
+        downloader.set_cache(cache);
+    }
+    downloader.set_mirror_mode(opts.mirror);
+    if let Some(template) = opts.output_template {
+        downloader.set_path_template(PathTemplate::parse(&template).unwrap());
+    }
+
+    let hls = opts.hls.as_deref().map(|variant| match variant {
+        "highest" => HlsVariant::Highest,
+        "lowest" => HlsVariant::Lowest,
+        bandwidth => HlsVariant::MaxBandwidth(bandwidth.parse().unwrap()),
     });
+    let tasks = Box::new(tasks.map(move |mut task| {
+        task.hls = hls;
+        task
+    }));
+
+    if let Some(Command::Verify) = opts.command {
+        for task in tasks {
+            if opts.globbing {
+                downloader.append_pattern(task).unwrap();
+            } else {
+                downloader.append_task(task);
+            }
+        }
+        let verification = downloader.verify().unwrap();
+        let mut problems = verification.extraneous.len();
+        for report in &verification.reports {
+            if let Err(e) = &report.result {
+                problems += 1;
+                println!("{}", style(format!("{} [{}]", report.url, e)).red());
+            }
+        }
+        for file in &verification.extraneous {
+            println!(
+                "{}",
+                style(format!("Extraneous: {}", file.display())).yellow()
+            );
+        }
+        println!(
+            "Verify Complete! {} Tasks, {} Failed, {} Extraneous.",
+            verification.reports.len(),
+            problems - verification.extraneous.len(),
+            verification.extraneous.len()
+        );
+        std::process::exit(if problems == 0 { 0 } else { 1 });
+    }
 
-    let bar = ProgressBar::new(tasks.len() as u64);
+    let bar = ProgressBar::new(0);
     bar.set_style(
         ProgressStyle::default_bar()
             .template(
@@ -172,6 +585,59 @@ fn main() {
             ).unwrap()
             .progress_chars("##-"),
     );
+    let producer = if stream {
+        // downloading starts before the input ends
+        let sender = downloader.task_channel(opts.jobs);
+        let bar = bar.clone();
+        let globbing = opts.globbing;
+        Some(thread::spawn(move || {
+            for task in tasks {
+                let pattern = globbing.then(|| UrlPattern::parse(&task.url).unwrap());
+                let urls: Box<dyn Iterator<Item = String>> = match &pattern {
+                    Some(pattern) => Box::new(pattern.iter()),
+                    None => Box::new(std::iter::once(task.url.clone())),
+                };
+                for url in urls {
+                    bar.inc_length(1);
+                    let task = shirodl::DownloadTask {
+                        url,
+                        ..task.clone()
+                    };
+                    // the batch is gone, it reports why
+                    if sender.blocking_send(task).is_err() {
+                        bar.dec_length(1);
+                        return;
+                    }
+                }
+            }
+        }))
+    } else {
+        let filter = LinkFilter {
+            extensions: opts
+                .accept_ext
+                .as_deref()
+                .map(|e| e.split(',').map(str::to_string).collect())
+                .unwrap_or_default(),
+            pattern: opts.accept_regex.clone(),
+            depth: opts.depth,
+        };
+        let mut task_count = 0;
+        tasks.into_iter().for_each(|task| {
+            if opts.extract_links {
+                match downloader.append_links(&task.url, &filter) {
+                    Ok(count) => task_count += count,
+                    Err(e) => println!("{}", style(format!("{} [{}]", task.url, e)).red()),
+                }
+            } else if opts.globbing {
+                task_count += downloader.append_pattern(task).unwrap();
+            } else {
+                downloader.append_task(task);
+                task_count += 1;
+            }
+        });
+        bar.set_length(task_count as u64);
+        None
+    };
     let (sender, receiver) = mpsc::channel();
     let retain_sender = sender.clone();
     bar.enable_steady_tick(Duration::from_millis(200));
@@ -203,20 +669,23 @@ fn main() {
                 } else {
                     Emoji::new("❌️", "[Failed]")
                 },
-                if err.is_none() {
-                    url.to_string()
+                if let Some(err) = err {
+                    format!("{} [{}]", url, err)
                 } else {
-                    format!("{} [{}]", url, err.unwrap())
+                    url.to_string()
                 }
             );
             sender.send(Some(msg_style.apply_to(msg).to_string()));
         })
         .unwrap();
+    if let Some(producer) = producer {
+        producer.join().unwrap();
+    }
     retain_sender.send(None);
     display_thread.join().unwrap();
     let failed_unignorable: Vec<_> = failed.iter().filter(|v| !v.err.ignorable()).collect();
     println!("Download Complete!");
-    if failed.len() != 0 {
+    if !failed.is_empty() {
         println!(
             "{} Failed, {} Ignorable, {} Unignorable.",
             failed.len(),
@@ -231,11 +700,36 @@ fn main() {
                 url: v.url.clone(),
                 filename: v.filename.clone(),
                 folder: Some(v.path.clone()),
+                redirects: v.redirects.clone(),
                 error: v.err.to_string(),
             })
             .collect::<Vec<DownloadFailedSerializable>>();
         std::fs::write(save_failed, serde_json::to_string_pretty(&failed).unwrap()).unwrap();
     }
+    // the whole batch stopped, the tasks left are among the failed ones
+    if let Some(stopped) = failed.iter().find(|v| v.err.stops_batch()) {
+        println!(
+            "{}",
+            style(format!("Download Stopped: {}", stopped.err))
+                .red()
+                .bold()
+        );
+        std::process::exit(1);
+    }
+}
+
+fn is_archive(v: &str) -> Result<(), String> {
+    match ArchiveFormat::from_path(Path::new(v)) {
+        Some(_) => Ok(()),
+        None => Err("Not a `.tar`, `.tar.gz`, `.tar.zst` or `.zip` file.".to_string()),
+    }
+}
+
+fn is_manifest(v: &str) -> Result<(), String> {
+    match ManifestFormat::from_path(Path::new(v)) {
+        Some(_) => Ok(()),
+        None => Err("Not a `B3SUMS`, `SHA256SUMS` or `.jsonl` file.".to_string()),
+    }
 }
 
 fn is_existed_as_file(v: &str) -> Result<(), String> {
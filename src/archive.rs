@@ -0,0 +1,285 @@
+#[cfg(test)]
+mod tests {
+    use super::ArchiveFormat;
+    use crate::{ConflictPolicy, DownloadTask, Downloader, Error};
+    use std::io::Read;
+    use std::path::{Path, PathBuf};
+
+    fn download(archive: &Path, format: ArchiveFormat) -> Vec<Result<(), Error>> {
+        let mut dler = Downloader::new();
+        dler.set_destination(PathBuf::from("unused"));
+        dler.set_archive(archive.to_path_buf(), format);
+        dler.set_conflict_policy(ConflictPolicy::KeepBoth);
+        for (content, path) in [("one", "a"), ("two", "a/b"), ("three", "a")] {
+            dler.append_task(DownloadTask::new(
+                format!("data:text/plain,{}", content),
+                PathBuf::from(path),
+                Some("x.txt".to_string()),
+            ));
+        }
+        let reports = dler.download_report(|_, _, _, _| {}).unwrap();
+        reports.into_iter().map(|r| r.result).collect()
+    }
+
+    #[test]
+    fn tar() {
+        let dir = std::env::temp_dir().join(format!("shirodl-archive-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let archive = dir.join("out.tar.gz");
+        let format = ArchiveFormat::from_path(&archive).unwrap();
+        assert!(download(&archive, format).iter().all(Result::is_ok));
+        let decoder = flate2::read::GzDecoder::new(std::fs::File::open(&archive).unwrap());
+        let mut entries: Vec<(String, String)> = tar::Archive::new(decoder)
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                let name = entry.path().unwrap().to_string_lossy().to_string();
+                let mut content = String::new();
+                entry.read_to_string(&mut content).unwrap();
+                (name, content)
+            })
+            .collect();
+        entries.sort();
+        let mut expected = vec!["a/x.txt", "a/x (1).txt", "a/b/x.txt"];
+        expected.sort();
+        let names: Vec<&str> = entries.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, expected);
+        assert!(!Path::new("unused").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn zip() {
+        let dir = std::env::temp_dir().join(format!("shirodl-zip-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let archive = dir.join("out.zip");
+        assert!(download(&archive, ArchiveFormat::Zip)
+            .iter()
+            .all(Result::is_ok));
+        let mut zip = zip::ZipArchive::new(std::fs::File::open(&archive).unwrap()).unwrap();
+        assert_eq!(zip.len(), 3);
+        let mut content = String::new();
+        let mut entry = zip.by_name("a/b/x.txt").unwrap();
+        entry.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "two");
+        assert!(entry.last_modified().year() >= 2024);
+        drop(entry);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+use crate::date;
+use crate::{ConflictPolicy, Error};
+use flate2::write::GzEncoder;
+use reqwest::header::{HeaderMap, LAST_MODIFIED};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// finished files waiting for the writer, senders block when it falls behind
+const QUEUED_ENTRIES: usize = 64;
+
+// container written by `Downloader::set_archive`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    TarGzip,
+    TarZstd,
+    // entries are deflated
+    Zip,
+}
+
+impl ArchiveFormat {
+    // guess from the extension: `.tar`, `.tar.gz`/`.tgz`, `.tar.zst`/`.tzst` or `.zip`
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_ascii_lowercase();
+        if name.ends_with(".tar") {
+            Some(Self::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Self::TarGzip)
+        } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Some(Self::TarZstd)
+        } else if name.ends_with(".zip") {
+            Some(Self::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+struct Entry {
+    name: String,
+    content: Vec<u8>,
+    modified: SystemTime,
+}
+
+// writes finished files into one archive from its own thread, named below the destination
+pub(crate) struct ArchiveWriter {
+    root: PathBuf,
+    // hash of every entry written so far, an archive cannot replace an entry
+    names: Mutex<HashMap<String, blake3::Hash>>,
+    sender: Mutex<Option<SyncSender<Entry>>>,
+    thread: Mutex<Option<JoinHandle<io::Result<()>>>>,
+}
+
+impl ArchiveWriter {
+    pub(crate) fn create(path: &Path, format: ArchiveFormat, root: PathBuf) -> Result<Self, Error> {
+        let file = File::create(path).map_err(|e| Error::IoError(e.to_string()))?;
+        let (sender, receiver) = mpsc::sync_channel(QUEUED_ENTRIES);
+        let thread = thread::spawn(move || match format {
+            ArchiveFormat::Tar => write_tar(file, receiver)?.flush(),
+            ArchiveFormat::TarGzip => {
+                let encoder = GzEncoder::new(file, flate2::Compression::default());
+                write_tar(encoder, receiver)?.finish().map(drop)
+            }
+            ArchiveFormat::TarZstd => {
+                let encoder = zstd::Encoder::new(file, 0)?;
+                write_tar(encoder, receiver)?.finish().map(drop)
+            }
+            ArchiveFormat::Zip => write_zip(file, receiver),
+        });
+        Ok(Self {
+            root,
+            names: Mutex::new(HashMap::new()),
+            sender: Mutex::new(Some(sender)),
+            thread: Mutex::new(Some(thread)),
+        })
+    }
+
+    // `/` separated path below the root, without `.` components
+    fn entry_name(&self, filepath: &Path) -> Result<String, Error> {
+        let relative = filepath.strip_prefix(&self.root).unwrap_or(filepath);
+        let mut parts = vec![];
+        for component in relative.components() {
+            match component {
+                Component::Normal(part) => parts.push(part.to_string_lossy()),
+                Component::CurDir => {}
+                _ => {
+                    return Err(Error::IoError(format!(
+                        "Illegal Entry Name: {:?}",
+                        relative
+                    )))
+                }
+            }
+        }
+        Ok(parts.join("/"))
+    }
+
+    // queue `content` for `filepath`, settling a taken name like the conflict policy does
+    pub(crate) fn add(
+        &self,
+        filepath: &Path,
+        content: &[u8],
+        headers: &HeaderMap,
+        conflict: ConflictPolicy,
+    ) -> Result<String, Error> {
+        let mut name = self.entry_name(filepath)?;
+        let hash = blake3::hash(content);
+        {
+            let mut names = self.names.lock().unwrap();
+            if let Some(existed) = names.get(&name) {
+                match conflict {
+                    ConflictPolicy::CompareHash if *existed == hash => return Ok(name),
+                    ConflictPolicy::CompareHash => return Err(Error::DifferentFileExisted),
+                    ConflictPolicy::KeepBoth => name = numbered(&name, |n| names.contains_key(n)),
+                    _ => return Err(Error::FileExisted),
+                }
+            }
+            names.insert(name.clone(), hash);
+        }
+        let modified = headers
+            .get(LAST_MODIFIED)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| httpdate::parse_http_date(v).ok())
+            .unwrap_or_else(SystemTime::now);
+        let entry = Entry {
+            name: name.clone(),
+            content: content.to_vec(),
+            modified,
+        };
+        let sender = self.sender.lock().unwrap().clone();
+        match sender.map(|sender| sender.send(entry)) {
+            Some(Ok(())) => Ok(name),
+            _ => Err(Error::IoError("Archive Writer Stopped".to_string())),
+        }
+    }
+
+    // wait for every queued entry to be written and close the archive
+    pub(crate) fn finish(&self) -> Result<(), Error> {
+        self.sender.lock().unwrap().take();
+        match self.thread.lock().unwrap().take() {
+            Some(thread) => thread
+                .join()
+                .unwrap()
+                .map_err(|e| Error::IoError(e.to_string())),
+            None => Ok(()),
+        }
+    }
+}
+
+// `name (1).ext`, like `ConflictPolicy::KeepBoth` names files
+fn numbered(name: &str, taken: impl Fn(&str) -> bool) -> String {
+    let (dir, file) = match name.rsplit_once('/') {
+        Some((dir, file)) => (format!("{}/", dir), file),
+        None => (String::new(), name),
+    };
+    let (stem, ext) = match file.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, format!(".{}", ext)),
+        _ => (file, String::new()),
+    };
+    (1..)
+        .map(|n| format!("{}{} ({}){}", dir, stem, n, ext))
+        .find(|candidate| !taken(candidate))
+        .unwrap()
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+fn write_tar<W: Write>(writer: W, receiver: Receiver<Entry>) -> io::Result<W> {
+    let mut builder = tar::Builder::new(writer);
+    for entry in receiver {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_size(entry.content.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(unix_seconds(entry.modified));
+        builder.append_data(&mut header, &entry.name, &entry.content[..])?;
+    }
+    builder.into_inner()
+}
+
+fn write_zip(file: File, receiver: Receiver<Entry>) -> io::Result<()> {
+    let mut zip = zip::ZipWriter::new(file);
+    for entry in receiver {
+        let options = zip::write::FileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+            .last_modified_time(dos_time(entry.modified));
+        zip.start_file(entry.name, options)?;
+        zip.write_all(&entry.content)?;
+    }
+    zip.finish()?.flush()
+}
+
+// zip stores local calendar time without a zone, UTC is used
+fn dos_time(time: SystemTime) -> zip::DateTime {
+    let seconds = unix_seconds(time);
+    let (days, seconds) = ((seconds / 86400) as i64, seconds % 86400);
+    let (year, month, day) = date::civil_from_days(days);
+    zip::DateTime::from_date_and_time(
+        year as u16,
+        month,
+        day,
+        (seconds / 3600) as u8,
+        (seconds % 3600 / 60) as u8,
+        (seconds % 60) as u8,
+    )
+    .unwrap_or_default()
+}
@@ -0,0 +1,252 @@
+#[cfg(test)]
+#[allow(clippy::items_after_test_module)]
+mod tests {
+    use super::Cache;
+    use crate::{DownloadTask, Downloader};
+    use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE, ETAG};
+    use reqwest::Url;
+    use std::io::{Read, Write};
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn entries() {
+        let dir = std::env::temp_dir().join(format!("shirodl-cache-{}", std::process::id()));
+        let cache = Cache::open(dir.clone()).unwrap();
+        let url = Url::parse("https://example.com/a.jpg").unwrap();
+        assert!(cache.lookup(&url).is_none());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, HeaderValue::from_static("\"v1\""));
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("image/jpeg"));
+        let object = cache.insert(b"jpeg").unwrap();
+        assert_eq!(cache.insert(b"jpeg").unwrap(), object);
+        cache.remember(&url, b"jpeg", &headers).unwrap();
+
+        let cached = cache.lookup(&url).unwrap();
+        assert_eq!(cached.object, object);
+        assert_eq!(cached.read().unwrap(), b"jpeg");
+        assert_eq!(cached.headers, headers);
+        // the entry is gone with its object
+        std::fs::remove_file(object).unwrap();
+        assert!(cache.lookup(&url).is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn downloads() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/a.bin", listener.local_addr().unwrap());
+        let (bodies, revalidated) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let counters = (Arc::clone(&bodies), Arc::clone(&revalidated));
+        std::thread::spawn(move || {
+            for socket in listener.incoming() {
+                let mut socket = socket.unwrap();
+                let mut buf = [0; 1024];
+                let n = socket.read(&mut buf).unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+                let response = if request.contains("if-none-match: \"v1\"") {
+                    counters.1.fetch_add(1, Ordering::SeqCst);
+                    "HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\nConnection: close\r\n\r\n"
+                } else {
+                    counters.0.fetch_add(1, Ordering::SeqCst);
+                    "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: 4\r\n\
+                        Connection: close\r\n\r\nbody"
+                };
+                socket.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        let dir = std::env::temp_dir().join(format!("shirodl-cached-{}", std::process::id()));
+        let download = |folder: &str, size: Option<u64>| {
+            let mut dler = Downloader::new();
+            dler.set_destination(dir.join(folder));
+            dler.set_cache(dir.join("cache"));
+            let mut task = DownloadTask::new(url.clone(), PathBuf::from("."), None);
+            task.size = size;
+            dler.append_task(task);
+            let reports = dler.download_report(|_, _, _, _| {}).unwrap();
+            assert!(reports[0].result.is_ok());
+            std::fs::read(dir.join(folder).join("a.bin")).unwrap()
+        };
+        assert_eq!(download("first", None), b"body");
+        assert_eq!(download("second", None), b"body");
+        assert_eq!(download("third", Some(4)), b"body");
+        assert_eq!(bodies.load(Ordering::SeqCst), 1);
+        assert_eq!(revalidated.load(Ordering::SeqCst), 1);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let inode = |folder: &str| {
+                std::fs::metadata(dir.join(folder).join("a.bin"))
+                    .unwrap()
+                    .ino()
+            };
+            assert_eq!(inode("first"), inode("third"));
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copies_with_metadata() {
+        use std::os::unix::fs::MetadataExt;
+        let dir = std::env::temp_dir().join(format!("shirodl-cache-mtime-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("source.bin");
+        std::fs::write(&source, b"body").unwrap();
+        let url = Url::from_file_path(&source).unwrap().to_string();
+        let inode = |folder: &str, mtime: bool| {
+            let mut dler = Downloader::new();
+            dler.set_destination(dir.join(folder));
+            dler.set_cache(dir.join("cache"));
+            dler.set_mtime_from_server(mtime);
+            dler.append_task(DownloadTask::new(url.clone(), PathBuf::from("."), None));
+            let reports = dler.download_report(|_, _, _, _| {}).unwrap();
+            assert!(reports[0].result.is_ok());
+            std::fs::metadata(dir.join(folder).join("source.bin"))
+                .unwrap()
+                .ino()
+        };
+        let linked = inode("first", false);
+        assert_eq!(inode("second", false), linked);
+        // the mtime would otherwise be set on the cached object too
+        assert_ne!(inode("third", true), linked);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+use crate::Error;
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
+use reqwest::Url;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// response headers kept with a cached url, the validators and what saving looks at
+const KEPT_HEADERS: [&str; 3] = ["etag", "last-modified", "content-type"];
+
+// tells apart concurrent writes of the same object
+static NEXT_PARTIAL: AtomicUsize = AtomicUsize::new(0);
+
+fn io_error(e: std::io::Error) -> Error {
+    Error::IoError(e.to_string())
+}
+
+// `objects/<2 hex>/<blake3>` holds each content once, `urls/<blake3 of url>.json` what a url gave
+pub(crate) struct Cache {
+    dir: PathBuf,
+}
+
+// a url found in the cache
+pub(crate) struct Cached {
+    pub(crate) object: PathBuf,
+    pub(crate) headers: HeaderMap,
+}
+
+impl Cached {
+    pub(crate) fn read(&self) -> Result<Vec<u8>, Error> {
+        fs::read(&self.object).map_err(io_error)
+    }
+
+    // ask the server to answer `304 Not Modified` when the cached content is still current
+    pub(crate) fn add_validators(&self, headers: &mut HeaderMap) {
+        if let Some(etag) = self.headers.get(ETAG) {
+            headers.insert(IF_NONE_MATCH, etag.clone());
+        }
+        if let Some(modified) = self.headers.get(LAST_MODIFIED) {
+            headers.insert(IF_MODIFIED_SINCE, modified.clone());
+        }
+    }
+}
+
+impl Cache {
+    pub(crate) fn open(dir: PathBuf) -> Result<Self, Error> {
+        for sub in ["objects", "urls"] {
+            fs::create_dir_all(dir.join(sub)).map_err(|_| Error::FailedToCreateFolder)?;
+        }
+        Ok(Self { dir })
+    }
+
+    fn object_path(&self, hash: &blake3::Hash) -> PathBuf {
+        let hex = hash.to_hex();
+        self.dir.join("objects").join(&hex[..2]).join(hex.as_str())
+    }
+
+    fn entry_path(&self, url: &Url) -> PathBuf {
+        let key = blake3::hash(url.as_str().as_bytes()).to_hex();
+        self.dir.join("urls").join(format!("{}.json", key))
+    }
+
+    // write to a unique temporary name first, so readers never see a partial file
+    fn write_atomic(path: &Path, content: &[u8]) -> Result<(), Error> {
+        let mut partial = path.as_os_str().to_owned();
+        let unique = NEXT_PARTIAL.fetch_add(1, Ordering::Relaxed);
+        partial.push(format!(".{}-{}.part", std::process::id(), unique));
+        let partial = PathBuf::from(partial);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|_| Error::FailedToCreateFolder)?;
+        }
+        fs::write(&partial, content)
+            .and_then(|_| fs::rename(&partial, path))
+            .map_err(|e| {
+                let _ = fs::remove_file(&partial);
+                io_error(e)
+            })
+    }
+
+    // store `content` unless it is there already, returns the path of its object
+    pub(crate) fn insert(&self, content: &[u8]) -> Result<PathBuf, Error> {
+        let object = self.object_path(&blake3::hash(content));
+        if !object.is_file() {
+            Self::write_atomic(&object, content)?;
+        }
+        Ok(object)
+    }
+
+    // record that `url` answered with `content` and `headers`
+    pub(crate) fn remember(
+        &self,
+        url: &Url,
+        content: &[u8],
+        headers: &HeaderMap,
+    ) -> Result<(), Error> {
+        let mut kept = Map::new();
+        for name in KEPT_HEADERS {
+            if let Some(value) = headers.get(name).and_then(|v| v.to_str().ok()) {
+                kept.insert(name.to_string(), Value::from(value));
+            }
+        }
+        let entry = serde_json::json!({
+            "url": url.as_str(),
+            "hash": blake3::hash(content).to_hex().as_str(),
+            "headers": kept,
+        });
+        Self::write_atomic(&self.entry_path(url), entry.to_string().as_bytes())
+    }
+
+    // the object and headers `url` gave last time, `None` when unknown or its object is gone
+    pub(crate) fn lookup(&self, url: &Url) -> Option<Cached> {
+        let entry = fs::read(self.entry_path(url)).ok()?;
+        let entry: Value = serde_json::from_slice(&entry).ok()?;
+        if entry["url"].as_str() != Some(url.as_str()) {
+            return None;
+        }
+        let hash = blake3::Hash::from_hex(entry["hash"].as_str()?).ok()?;
+        let object = self.object_path(&hash);
+        if !object.is_file() {
+            return None;
+        }
+        let mut headers = HeaderMap::new();
+        for name in KEPT_HEADERS {
+            if let Some(value) = entry["headers"][name].as_str() {
+                let value = HeaderValue::from_str(value).ok()?;
+                headers.insert(HeaderName::from_static(name), value);
+            }
+        }
+        Some(Cached { object, headers })
+    }
+}
@@ -0,0 +1,168 @@
+#[cfg(test)]
+mod tests {
+    use super::{Checksum, HashAlgorithm};
+
+    #[test]
+    fn verify() {
+        let checksum = Checksum::parse(
+            "sha-256=2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824",
+        )
+        .unwrap();
+        assert_eq!(checksum.algorithm, HashAlgorithm::Sha256);
+        assert!(checksum.verify(b"hello"));
+        assert!(!checksum.verify(b"world"));
+        assert!(Checksum::parse("md5=5d41402abc4b2a76b9719d911017c592")
+            .unwrap()
+            .verify(b"hello"));
+        assert!(Checksum::parse("sha-256=2cf2").is_err());
+        assert!(Checksum::parse("adler32=062c0215").is_err());
+    }
+
+    #[test]
+    fn streamed() {
+        let data = vec![7; 200 * 1024];
+        for name in [
+            "blake3", "md5", "sha1", "sha224", "sha256", "sha384", "sha512",
+        ] {
+            let algorithm = HashAlgorithm::parse(name).unwrap();
+            let streamed = algorithm.digest_reader(&mut &data[..]).unwrap();
+            assert_eq!(streamed, algorithm.digest(&data));
+        }
+    }
+}
+
+use crate::Error;
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha224, Sha256, Sha384, Sha512};
+use std::io::{self, Read};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm {
+    Blake3,
+    Md5,
+    Sha1,
+    Sha224,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl HashAlgorithm {
+    // accepts the names used by aria2 and metalink, e.g. `sha-256`, `SHA256` or `md5`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().replace('-', "").as_str() {
+            "blake3" => Some(Self::Blake3),
+            "md5" => Some(Self::Md5),
+            "sha1" => Some(Self::Sha1),
+            "sha224" => Some(Self::Sha224),
+            "sha256" => Some(Self::Sha256),
+            "sha384" => Some(Self::Sha384),
+            "sha512" => Some(Self::Sha512),
+            _ => None,
+        }
+    }
+
+    pub fn digest(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Blake3 => blake3::hash(data).as_bytes().to_vec(),
+            Self::Md5 => Md5::digest(data).to_vec(),
+            Self::Sha1 => Sha1::digest(data).to_vec(),
+            Self::Sha224 => Sha224::digest(data).to_vec(),
+            Self::Sha256 => Sha256::digest(data).to_vec(),
+            Self::Sha384 => Sha384::digest(data).to_vec(),
+            Self::Sha512 => Sha512::digest(data).to_vec(),
+        }
+    }
+
+    // like `digest`, reading `reader` to the end a chunk at a time
+    pub(crate) fn digest_reader(&self, reader: &mut dyn Read) -> io::Result<Vec<u8>> {
+        match self {
+            Self::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+                io::copy(reader, &mut hasher)?;
+                Ok(hasher.finalize().as_bytes().to_vec())
+            }
+            Self::Md5 => stream::<Md5>(reader),
+            Self::Sha1 => stream::<Sha1>(reader),
+            Self::Sha224 => stream::<Sha224>(reader),
+            Self::Sha256 => stream::<Sha256>(reader),
+            Self::Sha384 => stream::<Sha384>(reader),
+            Self::Sha512 => stream::<Sha512>(reader),
+        }
+    }
+}
+
+fn stream<D: Digest>(reader: &mut dyn Read) -> io::Result<Vec<u8>> {
+    let mut hasher = D::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => return Ok(hasher.finalize().to_vec()),
+            Ok(n) => hasher.update(&buf[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+impl std::fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Blake3 => "blake3",
+            Self::Md5 => "md5",
+            Self::Sha1 => "sha-1",
+            Self::Sha224 => "sha-224",
+            Self::Sha256 => "sha-256",
+            Self::Sha384 => "sha-384",
+            Self::Sha512 => "sha-512",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// expected digest of a downloaded file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checksum {
+    pub algorithm: HashAlgorithm,
+    pub digest: Vec<u8>,
+}
+
+impl Checksum {
+    pub fn new(algorithm: HashAlgorithm, hex_digest: &str) -> Result<Self, Error> {
+        let illegal = || Error::ChecksumIllegal(format!("{}={}", algorithm, hex_digest));
+        let digest = decode_hex(hex_digest.trim()).ok_or_else(illegal)?;
+        if digest.len() != algorithm.digest(b"").len() {
+            return Err(illegal());
+        }
+        Ok(Self { algorithm, digest })
+    }
+
+    // parse `type=digest` as written in aria2 input files, e.g. `sha-256=0a1b...`
+    pub fn parse(checksum: &str) -> Result<Self, Error> {
+        let (algorithm, digest) = checksum
+            .split_once('=')
+            .ok_or_else(|| Error::ChecksumIllegal(checksum.to_string()))?;
+        let algorithm = HashAlgorithm::parse(algorithm)
+            .ok_or_else(|| Error::ChecksumIllegal(checksum.to_string()))?;
+        Self::new(algorithm, digest)
+    }
+
+    pub fn verify(&self, data: &[u8]) -> bool {
+        self.algorithm.digest(data) == self.digest
+    }
+}
+
+pub(crate) fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
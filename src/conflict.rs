@@ -0,0 +1,282 @@
+#[cfg(test)]
+mod tests {
+    use super::{resolve, ConflictPolicy};
+    use crate::storage::{LocalStorage, Metadata, Storage};
+    use crate::{DownloadTask, Downloader, Error};
+    use reqwest::header::HeaderMap;
+    use std::fs;
+    use std::io::{self, Read, Write};
+    use std::path::{Path, PathBuf};
+
+    // the local filesystem, where moving the written file into place fails
+    struct NoRename;
+
+    impl Storage for NoRename {
+        fn metadata(&self, path: &Path) -> Option<Metadata> {
+            LocalStorage.metadata(path)
+        }
+
+        fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+            LocalStorage.create_dir_all(path)
+        }
+
+        fn create(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+            LocalStorage.create(path)
+        }
+
+        fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+            LocalStorage.open(path)
+        }
+
+        fn hash(&self, path: &Path) -> io::Result<blake3::Hash> {
+            LocalStorage.hash(path)
+        }
+
+        fn rename(&self, _from: &Path, _to: &Path) -> io::Result<()> {
+            Err(io::ErrorKind::PermissionDenied.into())
+        }
+
+        fn remove(&self, path: &Path) -> io::Result<()> {
+            LocalStorage.remove(path)
+        }
+    }
+
+    #[test]
+    fn policies() {
+        let dir = std::env::temp_dir().join(format!("shirodl-conflict-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("a.txt");
+        fs::write(&target, b"old").unwrap();
+        fs::write(dir.join("a (1).txt"), b"old").unwrap();
+        let headers = HeaderMap::new();
+        let resolve = |policy, content: &[u8]| {
+            resolve(&LocalStorage, &target, policy, content, &headers, false)
+        };
+
+        assert!(matches!(
+            resolve(ConflictPolicy::Skip, b"new"),
+            Err(Error::FileExisted)
+        ));
+        assert!(matches!(
+            resolve(ConflictPolicy::CompareHash, b"old"),
+            Ok(None)
+        ));
+        assert!(matches!(
+            resolve(ConflictPolicy::CompareHash, b"new"),
+            Err(Error::DifferentFileExisted)
+        ));
+        assert_eq!(
+            resolve(ConflictPolicy::Overwrite, b"new").unwrap(),
+            Some(target.clone())
+        );
+        assert_eq!(
+            resolve(ConflictPolicy::KeepBoth, b"new").unwrap(),
+            Some(dir.join("a (2).txt"))
+        );
+        // the name stays taken until it is written, so the next copy gets its own
+        assert_eq!(
+            resolve(ConflictPolicy::KeepBoth, b"newer").unwrap(),
+            Some(dir.join("a (3).txt"))
+        );
+        // no Last-Modified, keep the local copy
+        assert!(matches!(
+            resolve(ConflictPolicy::KeepNewer, b"new"),
+            Err(Error::FileExisted)
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn failed_keep_both() {
+        let dir = std::env::temp_dir().join(format!("shirodl-keep-both-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.txt"), b"old").unwrap();
+        let download = |quota| {
+            let mut dler = Downloader::new();
+            dler.set_destination(dir.clone());
+            dler.set_conflict_policy(ConflictPolicy::KeepBoth);
+            match quota {
+                Some(quota) => dler.set_quota(Some(quota)),
+                None => dler.set_storage(NoRename),
+            }
+            dler.append_task(DownloadTask::new(
+                "data:,new".to_string(),
+                PathBuf::new(),
+                Some("a.txt".to_string()),
+            ));
+            dler.download_report(|_, _, _, _| {})
+                .unwrap()
+                .remove(0)
+                .result
+        };
+        // over the quota, then failing to write
+        assert!(matches!(download(Some(1)), Err(Error::QuotaExceeded(1))));
+        assert!(matches!(download(None), Err(Error::IoError(_))));
+        // the reserved `a (1).txt` is gone with the failed content
+        let mut names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["a.txt"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+use crate::storage::Storage;
+use crate::Error;
+use reqwest::header::{HeaderMap, LAST_MODIFIED};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+// one `KeepBoth` search at a time, so storages without an atomic `create_new` also hand out
+// each name once
+static NUMBERING: Mutex<()> = Mutex::new(());
+
+// what to do when the file a task saves to already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    // keep the existing file without downloading, fails with `FileExisted`
+    Skip,
+    // download and compare by hash, fails with `DifferentFileExisted` when they differ
+    CompareHash,
+    // replace the existing file
+    Overwrite,
+    // save as `name (1).ext`, `name (2).ext`, ... next to the existing file
+    KeepBoth,
+    // replace the existing file only when `Last-Modified` is newer than it
+    KeepNewer,
+}
+
+// what is known about the file at the task's path before saving
+pub(crate) enum Existing {
+    None,
+    Hash(blake3::Hash),
+    Modified(SystemTime),
+}
+
+pub(crate) fn hash_file(storage: &dyn Storage, path: &Path) -> Result<blake3::Hash, Error> {
+    storage.hash(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound | std::io::ErrorKind::PermissionDenied => {
+            Error::IoError(e.to_string())
+        }
+        _ => Error::HashingError,
+    })
+}
+
+pub(crate) fn modified(storage: &dyn Storage, path: &Path) -> Result<SystemTime, Error> {
+    storage
+        .metadata(path)
+        .ok_or_else(|| Error::IoError(format!("{} not found", path.display())))?
+        .modified
+        .ok_or_else(|| Error::IoError("modification time unavailable".to_string()))
+}
+
+// unknown `Last-Modified` never counts as newer
+pub(crate) fn is_newer(headers: &HeaderMap, local: SystemTime) -> bool {
+    headers
+        .get(LAST_MODIFIED)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| httpdate::parse_http_date(v).ok())
+        .is_some_and(|remote| remote > local)
+}
+
+// the first free `name (n).ext`, held by an empty file until its `Reservation` is settled
+fn numbered(storage: &dyn Storage, target: &Path) -> Result<PathBuf, Error> {
+    let stem = target
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let ext = target
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    let _numbering = NUMBERING.lock().unwrap();
+    for n in 1.. {
+        let candidate = target.with_file_name(format!("{} ({}){}", stem, n, ext));
+        match storage.create_new(&candidate) {
+            Ok(()) => return Ok(candidate),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(Error::IoError(e.to_string())),
+        }
+    }
+    unreachable!()
+}
+
+// a name reserved by `numbered`, removed on drop unless the content made it there
+pub(crate) struct Reservation<'a> {
+    storage: &'a dyn Storage,
+    path: Option<PathBuf>,
+}
+
+impl<'a> Reservation<'a> {
+    // what `resolve` picked for `target`, only a different name is a reservation
+    pub(crate) fn new(storage: &'a dyn Storage, target: &Path, resolved: &Path) -> Self {
+        Self {
+            storage,
+            path: (target != resolved).then(|| resolved.to_path_buf()),
+        }
+    }
+
+    pub(crate) fn keep(mut self) {
+        self.path = None;
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        if let Some(path) = self.path.take() {
+            let _ = self.storage.remove(&path);
+        }
+    }
+}
+
+// where `content` saved to `target` goes, `None` when nothing needs writing
+pub(crate) fn resolve(
+    storage: &dyn Storage,
+    target: &Path,
+    policy: ConflictPolicy,
+    content: &[u8],
+    headers: &HeaderMap,
+    when_rename: bool,
+) -> Result<Option<PathBuf>, Error> {
+    let Some(metadata) = storage.metadata(target) else {
+        return Ok(Some(target.to_path_buf()));
+    };
+    if metadata.is_dir {
+        return Err(if when_rename {
+            Error::FileExistedAsFolderWhenRename
+        } else {
+            Error::FileExistedAsFolder
+        });
+    }
+    match policy {
+        // the content is already here, so a renamed duplicate is compared instead of skipped
+        ConflictPolicy::Skip if !when_rename => Err(Error::FileExisted),
+        ConflictPolicy::Skip | ConflictPolicy::CompareHash => {
+            let existed = hash_file(storage, target).map_err(|e| match e {
+                Error::IoError(e) if when_rename => Error::IoErrorWhenRename(e),
+                Error::HashingError if when_rename => Error::HashingErrorWhenRename,
+                e => e,
+            })?;
+            if blake3::hash(content) == existed {
+                Ok(None)
+            } else if when_rename {
+                Err(Error::DifferentFileExistedWhenRename)
+            } else {
+                Err(Error::DifferentFileExisted)
+            }
+        }
+        ConflictPolicy::Overwrite => Ok(Some(target.to_path_buf())),
+        ConflictPolicy::KeepBoth => numbered(storage, target).map(Some),
+        ConflictPolicy::KeepNewer => {
+            if is_newer(headers, modified(storage, target)?) {
+                Ok(Some(target.to_path_buf()))
+            } else {
+                Err(Error::FileExisted)
+            }
+        }
+    }
+}
@@ -0,0 +1,93 @@
+#[cfg(test)]
+#[allow(clippy::items_after_test_module)]
+mod tests {
+    use super::Dedupe;
+    use crate::DownloadTask;
+    use std::path::PathBuf;
+
+    fn task(url: &str) -> DownloadTask {
+        DownloadTask::new(url.to_string(), PathBuf::new(), None)
+    }
+
+    #[test]
+    fn window() {
+        let mut unbounded = Dedupe::new(None);
+        assert!(unbounded.insert(&task("a")));
+        assert!(!unbounded.insert(&task("a")));
+        assert!(unbounded.contains(&task("a")));
+
+        let mut window = Dedupe::new(Some(2));
+        for url in ["a", "b", "c"] {
+            assert!(window.insert(&task(url)));
+        }
+        // `a` fell out of the window
+        assert!(window.insert(&task("a")));
+        assert!(!window.insert(&task("c")));
+
+        let mut off = Dedupe::new(Some(0));
+        assert!(off.insert(&task("a")));
+        assert!(off.insert(&task("a")));
+    }
+}
+
+use crate::DownloadTask;
+use std::collections::{HashSet, VecDeque};
+
+// url, path and filename of a task, hashed so a key takes 16 bytes however long the url is
+type Key = [u8; 16];
+
+fn key(task: &DownloadTask) -> Key {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(task.url.as_bytes());
+    hasher.update(b"\0");
+    hasher.update(task.path.as_os_str().as_encoded_bytes());
+    if let Some(filename) = &task.filename {
+        hasher.update(b"\0");
+        hasher.update(filename.as_bytes());
+    }
+    let mut key = [0; 16];
+    key.copy_from_slice(&hasher.finalize().as_bytes()[..16]);
+    key
+}
+
+// tasks seen so far, only the last `window` of them when set, 0 lets every task through
+pub(crate) struct Dedupe {
+    seen: HashSet<Key>,
+    order: VecDeque<Key>,
+    window: Option<usize>,
+}
+
+impl Dedupe {
+    pub(crate) fn new(window: Option<usize>) -> Self {
+        Self {
+            seen: HashSet::new(),
+            order: VecDeque::new(),
+            window,
+        }
+    }
+
+    pub(crate) fn contains(&self, task: &DownloadTask) -> bool {
+        self.seen.contains(&key(task))
+    }
+
+    // `false` when the task is a duplicate
+    pub(crate) fn insert(&mut self, task: &DownloadTask) -> bool {
+        let Some(window) = self.window else {
+            return self.seen.insert(key(task));
+        };
+        if window == 0 {
+            return true;
+        }
+        let key = key(task);
+        if !self.seen.insert(key) {
+            return false;
+        }
+        self.order.push_back(key);
+        if self.order.len() > window {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,137 @@
+#[cfg(test)]
+mod tests {
+    use super::{parse_resolve, IpFamily};
+    use std::net::{IpAddr, SocketAddr};
+
+    #[test]
+    fn curl_style_override() {
+        let (host, addrs) = parse_resolve("Example.com:443:10.0.0.1,[::1]").unwrap();
+        assert_eq!(host, "example.com");
+        assert_eq!(
+            addrs,
+            vec![
+                "10.0.0.1".parse::<IpAddr>().unwrap(),
+                "::1".parse::<IpAddr>().unwrap()
+            ]
+        );
+        assert!(parse_resolve("example.com:10.0.0.1").is_err());
+    }
+
+    #[test]
+    fn family_filter() {
+        let addrs: Vec<SocketAddr> =
+            vec!["[::1]:0".parse().unwrap(), "127.0.0.1:0".parse().unwrap()];
+        assert!(IpFamily::PreferV4.apply(addrs.clone())[0].is_ipv4());
+        assert_eq!(IpFamily::V6Only.apply(addrs).len(), 1);
+    }
+}
+
+use crate::Error;
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+
+// which address families are used to connect
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpFamily {
+    Any,
+    // try the preferred family first and fall back to the other one
+    PreferV4,
+    PreferV6,
+    // never connect over the other family
+    V4Only,
+    V6Only,
+}
+
+impl IpFamily {
+    fn apply(self, mut addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+        match self {
+            IpFamily::Any => {}
+            IpFamily::PreferV4 => addrs.sort_by_key(|addr| !addr.is_ipv4()),
+            IpFamily::PreferV6 => addrs.sort_by_key(|addr| !addr.is_ipv6()),
+            IpFamily::V4Only => addrs.retain(|addr| addr.is_ipv4()),
+            IpFamily::V6Only => addrs.retain(|addr| addr.is_ipv6()),
+        }
+        addrs
+    }
+}
+
+// curl-style `host:port:addr[,addr]...`, the port is ignored like in reqwest's overrides
+pub(crate) fn parse_resolve(spec: &str) -> Result<(String, Vec<IpAddr>), Error> {
+    let illegal = || Error::ResolveOverrideIllegal(spec.to_string());
+    let mut parts = spec.splitn(3, ':');
+    let host = parts
+        .next()
+        .filter(|host| !host.is_empty())
+        .ok_or_else(illegal)?;
+    parts
+        .next()
+        .and_then(|port| port.parse::<u16>().ok())
+        .ok_or_else(illegal)?;
+    let addrs = parts
+        .next()
+        .ok_or_else(illegal)?
+        .split(',')
+        .map(|addr| {
+            addr.trim_start_matches('[')
+                .trim_end_matches(']')
+                .parse::<IpAddr>()
+                .map_err(|_| illegal())
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((host.to_lowercase(), addrs))
+}
+
+pub(crate) struct Resolver {
+    pub(crate) overrides: HashMap<String, Vec<IpAddr>>,
+    pub(crate) family: IpFamily,
+}
+
+impl Resolver {
+    // addresses of `host` on `port`, after the overrides and the family
+    pub(crate) async fn lookup(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        let host = host.to_lowercase();
+        let overridden = self.overrides.get(&host).cloned();
+        lookup(host, overridden, self.family, port).await
+    }
+}
+
+async fn lookup(
+    host: String,
+    overridden: Option<Vec<IpAddr>>,
+    family: IpFamily,
+    port: u16,
+) -> io::Result<Vec<SocketAddr>> {
+    let addrs = match overridden {
+        Some(ips) => ips
+            .into_iter()
+            .map(|ip| SocketAddr::new(ip, port))
+            .collect(),
+        None => tokio::net::lookup_host((host.as_str(), port))
+            .await?
+            .collect(),
+    };
+    let addrs = family.apply(addrs);
+    if addrs.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no {:?} address found for {}", family, host),
+        ));
+    }
+    Ok(addrs)
+}
+
+impl Resolve for Resolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_lowercase();
+        let overridden = self.overrides.get(&host).cloned();
+        let family = self.family;
+        Box::pin(async move {
+            // port 0 is replaced by the port of the url when connecting
+            let addrs = lookup(host, overridden, family, 0).await?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
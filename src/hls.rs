@@ -0,0 +1,531 @@
+#[cfg(test)]
+mod tests {
+    use super::{decrypt, parse, HlsVariant, Playlist};
+    use crate::{DownloadTask, Downloader, Error};
+    use aes::cipher::block_padding::Pkcs7;
+    use aes::cipher::{BlockEncryptMut, KeyIvInit};
+    use reqwest::Url;
+    use std::io::{Read, Write};
+    use std::path::PathBuf;
+
+    #[test]
+    fn master() {
+        let base = Url::parse("https://example.com/video/master.m3u8").unwrap();
+        let text = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=1280000,RESOLUTION=640x360,CODECS=\"avc1.4d401e,mp4a.40.2\"
+low/index.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=5000000,RESOLUTION=1920x1080
+https://cdn.example.com/high/index.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=2560000
+mid/index.m3u8
+";
+        let variants = match parse(text, &base).unwrap() {
+            Playlist::Master(variants) => variants,
+            Playlist::Media(_) => panic!("not a master playlist"),
+        };
+        let pick = |variant| HlsVariant::pick(variant, &variants).unwrap().to_string();
+        assert_eq!(
+            pick(HlsVariant::Highest),
+            "https://cdn.example.com/high/index.m3u8"
+        );
+        assert_eq!(
+            pick(HlsVariant::Lowest),
+            "https://example.com/video/low/index.m3u8"
+        );
+        assert_eq!(
+            pick(HlsVariant::MaxBandwidth(3000000)),
+            "https://example.com/video/mid/index.m3u8"
+        );
+        // nothing fits, take the smallest one
+        assert_eq!(
+            pick(HlsVariant::MaxBandwidth(1)),
+            "https://example.com/video/low/index.m3u8"
+        );
+    }
+
+    #[test]
+    fn media() {
+        let base = Url::parse("https://example.com/v/index.m3u8").unwrap();
+        let text = "#EXTM3U
+#EXT-X-MEDIA-SEQUENCE:7
+#EXT-X-MAP:URI=\"init.mp4\"
+#EXTINF:4.0,
+a.ts
+#EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\",IV=0x000102030405060708090a0b0c0d0e0f
+#EXTINF:4.0,
+#EXT-X-BYTERANGE:100@20
+b.ts
+#EXT-X-BYTERANGE:50
+b.ts
+#EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\"
+#EXTINF:4.0,
+c.ts
+#EXT-X-ENDLIST
+";
+        let media = match parse(text, &base).unwrap() {
+            Playlist::Media(media) => media,
+            Playlist::Master(_) => panic!("not a media playlist"),
+        };
+        assert_eq!(media.init.unwrap().url.path(), "/v/init.mp4");
+        let segments = media.segments;
+        assert_eq!(segments.len(), 4);
+        assert!(segments[0].key.is_none());
+        assert_eq!(segments[1].range, Some((20, 100)));
+        assert_eq!(segments[2].range, Some((120, 50)));
+        assert_eq!(segments[1].iv()[15], 0x0f);
+        // without IV the media sequence number is used
+        assert_eq!(segments[3].sequence, 10);
+        assert_eq!(segments[3].iv()[15], 10);
+
+        assert!(parse(
+            "#EXTM3U\n#EXT-X-KEY:METHOD=SAMPLE-AES,URI=\"k\"\na.ts\n",
+            &base
+        )
+        .is_err());
+        assert!(parse("a.ts\n", &base).is_err());
+        for range in ["0", "0@5", "x@5", "10@18446744073709551610"] {
+            let text = format!("#EXTM3U\n#EXT-X-BYTERANGE:{}\na.ts\n", range);
+            assert!(matches!(
+                parse(&text, &base),
+                Err(Error::PlaylistIllegal(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn aes() {
+        // NIST SP 800-38A F.2.1, first block, with PKCS#7 padding appended
+        let key = hex("2b7e151628aed2a6abf7158809cf4f3c");
+        let iv = hex("000102030405060708090a0b0c0d0e0f");
+        let data = hex("7649abac8119b246cee98e9b12e9197d8964e0b149c10b7b682e6e39aaeb731c");
+        let plain = decrypt(&data, &key.try_into().unwrap(), iv.try_into().unwrap()).unwrap();
+        assert_eq!(plain, hex("6bc1bee22e409f96e93d7e117393172a"));
+        assert!(decrypt(&data[..20], &[0; 16], [0; 16]).is_err());
+    }
+
+    #[test]
+    fn download() {
+        let key = [7u8; 16];
+        let encrypted = cbc::Encryptor::<aes::Aes128>::new(&key.into(), &[0u8; 16].into())
+            .encrypt_padded_vec_mut::<Pkcs7>(b"world");
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for socket in listener.incoming() {
+                let mut socket = socket.unwrap();
+                let mut buf = [0; 1024];
+                let n = socket.read(&mut buf).unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                let body: Vec<u8> = match request.split_whitespace().nth(1).unwrap() {
+                    "/master.m3u8" => b"#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=1\nlow.m3u8\n\
+                        #EXT-X-STREAM-INF:BANDWIDTH=2\nhigh.m3u8\n"
+                        .to_vec(),
+                    "/high.m3u8" => b"#EXTM3U\n#EXTINF:1,\n0.ts\n\
+                        #EXT-X-KEY:METHOD=AES-128,URI=\"key\",IV=0x0\n#EXTINF:1,\n1.ts\n"
+                        .to_vec(),
+                    "/0.ts" => b"hello ".to_vec(),
+                    "/1.ts" => encrypted.clone(),
+                    "/key" => key.to_vec(),
+                    _ => b"wrong variant".to_vec(),
+                };
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                socket.write_all(head.as_bytes()).unwrap();
+                socket.write_all(&body).unwrap();
+            }
+        });
+        let dir = std::env::temp_dir().join(format!("shirodl-hls-{}", std::process::id()));
+        let mut dler = Downloader::new();
+        dler.set_destination(dir.clone());
+        dler.set_task_count(1);
+        let mut task = DownloadTask::new(format!("{}/master.m3u8", base), PathBuf::new(), None);
+        task.hls = Some(HlsVariant::Highest);
+        dler.append_task(task);
+        let reports = dler.download_report(|_, _, _, _| {}).unwrap();
+        assert!(reports[0].result.is_ok());
+        assert_eq!(
+            std::fs::read(dir.join("master.ts")).unwrap(),
+            b"hello world"
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+}
+
+use crate::proxy::ProxyPool;
+use crate::{redirect, transfer, Downloader, Error, WorkerOptions};
+use aes::cipher::block_padding::Pkcs7;
+use aes::cipher::{BlockDecryptMut, KeyIvInit};
+use reqwest::header::{HeaderMap, HeaderValue, RANGE};
+use reqwest::{StatusCode, Url};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+// which stream of a master playlist is downloaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HlsVariant {
+    #[default]
+    Highest,
+    Lowest,
+    // the highest bandwidth not above the limit, or the lowest one when none fits
+    MaxBandwidth(u64),
+}
+
+impl HlsVariant {
+    fn pick(self, variants: &[(u64, Url)]) -> Option<&Url> {
+        let lowest = variants.iter().min_by_key(|(bandwidth, _)| *bandwidth);
+        match self {
+            HlsVariant::Highest => variants.iter().max_by_key(|(bandwidth, _)| *bandwidth),
+            HlsVariant::Lowest => lowest,
+            HlsVariant::MaxBandwidth(limit) => variants
+                .iter()
+                .filter(|(bandwidth, _)| *bandwidth <= limit)
+                .max_by_key(|(bandwidth, _)| *bandwidth)
+                .or(lowest),
+        }
+        .map(|(_, url)| url)
+    }
+}
+
+pub(crate) enum Playlist {
+    // bandwidth and uri of every variant
+    Master(Vec<(u64, Url)>),
+    Media(Box<MediaPlaylist>),
+}
+
+pub(crate) struct MediaPlaylist {
+    init: Option<Segment>,
+    segments: Vec<Segment>,
+}
+
+#[derive(Clone, PartialEq, Eq)]
+struct Key {
+    url: Url,
+    iv: Option<[u8; 16]>,
+}
+
+#[derive(Clone)]
+struct Segment {
+    url: Url,
+    // offset and length
+    range: Option<(u64, u64)>,
+    key: Option<Key>,
+    sequence: u64,
+}
+
+impl Segment {
+    fn iv(&self) -> [u8; 16] {
+        self.key
+            .as_ref()
+            .and_then(|key| key.iv)
+            .unwrap_or_else(|| (self.sequence as u128).to_be_bytes())
+    }
+}
+
+// `KEY=value,KEY="quoted, value"`
+fn attributes(list: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    let mut rest = list;
+    while let Some((key, after)) = rest.split_once('=') {
+        let (value, remaining) = match after.strip_prefix('"') {
+            Some(quoted) => match quoted.split_once('"') {
+                Some((value, remaining)) => (value, remaining),
+                None => (quoted, ""),
+            },
+            None => after.split_once(',').map_or((after, ""), |(v, r)| (v, r)),
+        };
+        attributes.insert(key.trim().to_ascii_uppercase(), value.to_string());
+        rest = remaining.trim_start_matches(',');
+    }
+    attributes
+}
+
+fn parse_iv(iv: &str) -> Option<[u8; 16]> {
+    let hex = iv.strip_prefix("0x").or_else(|| iv.strip_prefix("0X"))?;
+    u128::from_str_radix(hex, 16).ok().map(u128::to_be_bytes)
+}
+
+pub(crate) fn parse(text: &str, base: &Url) -> Result<Playlist, Error> {
+    let illegal = |reason: &str| Error::PlaylistIllegal(format!("{}: {}", reason, base));
+    let mut lines = text.lines().map(str::trim).filter(|l| !l.is_empty());
+    if lines.next() != Some("#EXTM3U") {
+        return Err(illegal("missing #EXTM3U"));
+    }
+    let join = |uri: &str| base.join(uri).map_err(|_| illegal("bad uri"));
+    let mut variants = vec![];
+    let mut bandwidth = None;
+    let mut segments = vec![];
+    let mut init = None;
+    let mut sequence = 0;
+    let mut key = None;
+    let mut range = None;
+    let mut next_offset = HashMap::new();
+    for line in lines {
+        if let Some(tag) = line.strip_prefix('#') {
+            let (name, value) = tag.split_once(':').unwrap_or((tag, ""));
+            match name {
+                "EXT-X-STREAM-INF" => {
+                    bandwidth = Some(
+                        attributes(value)
+                            .get("BANDWIDTH")
+                            .and_then(|b| b.parse().ok())
+                            .unwrap_or(0),
+                    );
+                }
+                "EXT-X-MEDIA-SEQUENCE" => {
+                    sequence = value.parse().map_err(|_| illegal("bad media sequence"))?;
+                }
+                "EXT-X-KEY" => {
+                    let attributes = attributes(value);
+                    key = match attributes.get("METHOD").map(String::as_str) {
+                        Some("NONE") => None,
+                        Some("AES-128") => Some(Key {
+                            url: join(attributes.get("URI").ok_or_else(|| illegal("key uri"))?)?,
+                            iv: match attributes.get("IV") {
+                                Some(iv) => Some(parse_iv(iv).ok_or_else(|| illegal("bad iv"))?),
+                                None => None,
+                            },
+                        }),
+                        method => {
+                            return Err(Error::EncryptionUnsupported(
+                                method.unwrap_or_default().to_string(),
+                            ))
+                        }
+                    };
+                }
+                "EXT-X-BYTERANGE" => {
+                    let (length, offset) = value.split_once('@').unwrap_or((value, ""));
+                    let length = length
+                        .parse()
+                        .ok()
+                        .filter(|&length: &u64| length > 0)
+                        .ok_or_else(|| illegal("bad byte range"))?;
+                    let offset = match offset {
+                        "" => None,
+                        offset => Some(offset.parse().map_err(|_| illegal("bad byte range"))?),
+                    };
+                    range = Some((offset, length));
+                }
+                "EXT-X-MAP" => {
+                    let attributes = attributes(value);
+                    let uri = attributes.get("URI").ok_or_else(|| illegal("map uri"))?;
+                    init = Some(Segment {
+                        url: join(uri)?,
+                        range: None,
+                        key: None,
+                        sequence: 0,
+                    });
+                }
+                _ => {}
+            }
+            continue;
+        }
+        let url = join(line)?;
+        if let Some(bandwidth) = bandwidth.take() {
+            variants.push((bandwidth, url));
+            continue;
+        }
+        // a range without offset continues where the previous one of the same uri ended
+        let range = match range.take() {
+            Some((offset, length)) => {
+                let offset: u64 =
+                    offset.unwrap_or_else(|| next_offset.get(&url).copied().unwrap_or(0));
+                let end = offset
+                    .checked_add(length)
+                    .ok_or_else(|| illegal("bad byte range"))?;
+                next_offset.insert(url.clone(), end);
+                Some((offset, length))
+            }
+            None => None,
+        };
+        segments.push(Segment {
+            url,
+            range,
+            key: key.clone(),
+            sequence,
+        });
+        sequence += 1;
+    }
+    if !variants.is_empty() {
+        Ok(Playlist::Master(variants))
+    } else {
+        Ok(Playlist::Media(Box::new(MediaPlaylist { init, segments })))
+    }
+}
+
+pub(crate) fn decrypt(data: &[u8], key: &[u8; 16], iv: [u8; 16]) -> Result<Vec<u8>, Error> {
+    cbc::Decryptor::<aes::Aes128>::new(key.into(), &iv.into())
+        .decrypt_padded_vec_mut::<Pkcs7>(data)
+        .map_err(|_| Error::DecryptionFailed)
+}
+
+// GET `url` or a range of it, retrying with another proxy like whole files do
+async fn fetch(
+    pool: &ProxyPool,
+    url: &Url,
+    range: Option<(u64, u64)>,
+    options: &WorkerOptions,
+    retries: usize,
+) -> Result<(Vec<u8>, HeaderMap), Error> {
+    let host = url.host_str().unwrap_or_default();
+    let mut headers = options.headers.clone();
+    if let Some((offset, length)) = range {
+        let value = format!("bytes={}-{}", offset, offset + length - 1);
+        headers.insert(RANGE, HeaderValue::from_str(&value).unwrap());
+    }
+    let mut attempt = 0;
+    let mut last_proxy = None;
+    loop {
+        let (proxy, client) = pool.pick(host, last_proxy);
+        let result = async {
+            let (_, response) =
+                redirect::fetch(client, url.clone(), &headers, options.redirect, &mut vec![])
+                    .await?;
+            let status = response.status();
+            let response_headers = response.headers().clone();
+            match status {
+                StatusCode::OK | StatusCode::PARTIAL_CONTENT => {}
+                StatusCode::NOT_FOUND => return Err(Error::ResourceNotFound),
+                status => return Err(Error::RequestNotOK(status.as_u16())),
+            }
+            let mut body = transfer::read_body(response, options.speed).await?;
+            // the server ignored the range and sent everything
+            if let (Some((offset, length)), StatusCode::OK) = (range, status) {
+                let start = (offset as usize).min(body.len());
+                let end = start.saturating_add(length as usize).min(body.len());
+                body = body[start..end].to_vec();
+            }
+            Ok((body, response_headers))
+        }
+        .await;
+        pool.report(proxy, &result);
+        match result {
+            Err(e) if e.retriable() && attempt < retries => {
+                attempt += 1;
+                last_proxy = proxy;
+            }
+            result => break result,
+        }
+    }
+}
+
+// `filename`, or the name of the playlist with `.ts` as extension
+pub(crate) fn output_name(url: &Url, filename: &Option<String>) -> Result<String, Error> {
+    Ok(match filename {
+        Some(filename) => filename.clone(),
+        None => {
+            let name = Downloader::resolve_filename(url, &None)?;
+            match name.rsplit_once('.') {
+                Some((stem, _)) if !stem.is_empty() => format!("{}.ts", stem),
+                _ => format!("{}.ts", name),
+            }
+        }
+    })
+}
+
+// download the playlist at `url` into one `.ts` file, segments take slots of `limits`
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn download(
+    pool: Arc<ProxyPool>,
+    limits: Arc<Semaphore>,
+    url: &str,
+    path: &Path,
+    filename: &Option<String>,
+    variant: HlsVariant,
+    options: &WorkerOptions,
+    retries: usize,
+) -> Result<(), Error> {
+    let origin = url;
+    let mut url = Url::parse(url).map_err(|_| Error::UrlIllegal)?;
+    let path = path.to_path_buf();
+    let filepath = path.join(output_name(&url, filename)?);
+    let existing = Downloader::check_existing(&path, &filepath, options)?;
+
+    let mut picked = false;
+    let (media, playlist_headers) = loop {
+        let (body, headers) = {
+            let _slot = limits.acquire().await.unwrap();
+            fetch(&pool, &url, None, options, retries).await?
+        };
+        match parse(&String::from_utf8_lossy(&body), &url)? {
+            Playlist::Media(media) => break (media, headers),
+            // a variant must be a media playlist
+            Playlist::Master(variants) if !picked => {
+                url = variant
+                    .pick(&variants)
+                    .ok_or_else(|| Error::PlaylistIllegal(url.to_string()))?
+                    .clone();
+                picked = true;
+            }
+            Playlist::Master(_) => return Err(Error::PlaylistIllegal(url.to_string())),
+        }
+    };
+
+    let mut keys: HashMap<Url, [u8; 16]> = HashMap::new();
+    for key in media.segments.iter().filter_map(|s| s.key.as_ref()) {
+        if !keys.contains_key(&key.url) {
+            let _slot = limits.acquire().await.unwrap();
+            let (body, _) = fetch(&pool, &key.url, None, options, retries).await?;
+            let body: [u8; 16] = body
+                .try_into()
+                .map_err(|_| Error::PlaylistIllegal(format!("key is not 16 bytes: {}", key.url)))?;
+            keys.insert(key.url.clone(), body);
+        }
+    }
+
+    let jobs: Vec<_> = media
+        .init
+        .into_iter()
+        .chain(media.segments)
+        .map(|segment| {
+            let pool = Arc::clone(&pool);
+            let limits = Arc::clone(&limits);
+            let options = options.clone();
+            let key = segment.key.as_ref().map(|key| keys[&key.url]);
+            tokio::spawn(async move {
+                let _slot = limits.acquire_owned().await.unwrap();
+                let (data, _) =
+                    fetch(&pool, &segment.url, segment.range, &options, retries).await?;
+                match key {
+                    Some(key) => decrypt(&data, &key, segment.iv()),
+                    None => Ok(data),
+                }
+            })
+        })
+        .collect();
+    let mut content = vec![];
+    let mut jobs = jobs.into_iter();
+    while let Some(job) = jobs.next() {
+        match job.await.unwrap() {
+            Ok(data) => content.extend(data),
+            Err(e) => {
+                jobs.for_each(|job| job.abort());
+                return Err(e);
+            }
+        }
+    }
+    if let Some(checksum) = &options.checksum {
+        if !checksum.verify(&content) {
+            return Err(Error::ChecksumMismatch(checksum.algorithm));
+        }
+    }
+    Downloader::save(
+        &filepath,
+        existing,
+        &content,
+        &playlist_headers,
+        origin,
+        options,
+    )
+    .await
+}
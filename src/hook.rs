@@ -0,0 +1,327 @@
+#[cfg(all(test, feature = "hooks-archive", feature = "hooks-image"))]
+mod tests {
+    use super::{Decompress, Extract, FinishedFile, Hook, VerifyImage};
+    use crate::{DownloadTask, Downloader, Error, LocalStorage};
+    use reqwest::header::HeaderMap;
+    use std::fs;
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+
+    fn finished<'a>(path: &Path, headers: &'a HeaderMap) -> FinishedFile<'a> {
+        FinishedFile {
+            path: path.to_path_buf(),
+            url: "https://example.com/",
+            headers,
+            hash: blake3::hash(&fs::read(path).unwrap()),
+            storage: &LocalStorage,
+        }
+    }
+
+    #[test]
+    fn decompress_and_extract() {
+        let dir = std::env::temp_dir().join(format!("shirodl-hook-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut tar = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mode(0o644);
+        tar.append_data(&mut header, "inner/a.txt", &b"hello"[..])
+            .unwrap();
+        let mut gzip = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        gzip.write_all(&tar.into_inner().unwrap()).unwrap();
+        fs::write(dir.join("data.tar.gz"), gzip.finish().unwrap()).unwrap();
+
+        let headers = HeaderMap::new();
+        let mut file = finished(&dir.join("data.tar.gz"), &headers);
+        Decompress::default().finished(&mut file).unwrap();
+        assert_eq!(file.path, dir.join("data.tar"));
+        assert!(!dir.join("data.tar.gz").exists());
+        Extract::default().finished(&mut file).unwrap();
+        assert_eq!(file.path, dir.join("data"));
+        assert_eq!(fs::read(dir.join("data/inner/a.txt")).unwrap(), b"hello");
+        assert!(!dir.join("data.tar").exists());
+
+        let mut zip = zip::ZipWriter::new(fs::File::create(dir.join("data.zip")).unwrap());
+        zip.start_file("inner/b.txt", Default::default()).unwrap();
+        zip.write_all(b"world").unwrap();
+        zip.finish().unwrap();
+        let mut file = finished(&dir.join("data.zip"), &headers);
+        Extract::default().finished(&mut file).unwrap();
+        assert_eq!(fs::read(dir.join("data/inner/b.txt")).unwrap(), b"world");
+        assert!(!dir.join("data.zip").exists());
+
+        // entries may not leave the folder
+        let mut tar = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mode(0o644);
+        header.as_gnu_mut().unwrap().name[..9].copy_from_slice(b"../a.txt\0");
+        header.set_cksum();
+        tar.append(&header, &b"hello"[..]).unwrap();
+        fs::write(dir.join("evil.tar"), tar.into_inner().unwrap()).unwrap();
+        let mut file = finished(&dir.join("evil.tar"), &headers);
+        assert!(matches!(
+            Extract::default().finished(&mut file),
+            Err(Error::HookFailed(_))
+        ));
+        assert!(!dir.join("a.txt").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn images() {
+        let dir = std::env::temp_dir().join(format!("shirodl-image-{}", std::process::id()));
+        let mut dler = Downloader::new();
+        dler.set_destination(dir.clone());
+        dler.add_hook(VerifyImage);
+        // a 1x1 png, and the same cut short
+        let png = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNk+M9QDwADhgGAWjR9awAAAABJRU5ErkJggg==";
+        for (content, name) in [(png, "good.png"), (&png[..40], "bad.png")] {
+            dler.append_task(DownloadTask::new(
+                format!("data:image/png;base64,{}", content),
+                PathBuf::new(),
+                Some(name.to_string()),
+            ));
+        }
+        let reports = dler.download_report(|_, _, _, _| {}).unwrap();
+        assert!(reports[0].result.is_ok());
+        assert!(matches!(reports[1].result, Err(Error::HookFailed(_))));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+use crate::storage::Storage;
+use crate::Error;
+use reqwest::header::HeaderMap;
+#[cfg(feature = "hooks-image")]
+use reqwest::header::CONTENT_TYPE;
+#[cfg(any(feature = "hooks-archive", feature = "hooks-image"))]
+use std::io::Read;
+#[cfg(feature = "hooks-archive")]
+use std::io::{self, BufReader, Seek, Write};
+#[cfg(feature = "hooks-archive")]
+use std::path::Component;
+#[cfg(any(feature = "hooks-archive", feature = "hooks-image"))]
+use std::path::Path;
+use std::path::PathBuf;
+
+// a file saved by the downloader, as seen by hooks
+pub struct FinishedFile<'a> {
+    // where the file is now, a hook that moves it updates this for the hooks after it
+    pub path: PathBuf,
+    // the url the content came from, after redirects
+    pub url: &'a str,
+    pub headers: &'a HeaderMap,
+    // blake3 hash of the downloaded content
+    pub hash: blake3::Hash,
+    // where the file was saved, hooks read and write through it
+    pub storage: &'a dyn Storage,
+}
+
+// run on every saved file on a blocking thread, an error fails the task but keeps the file
+pub trait Hook: Send + Sync {
+    fn finished(&self, file: &mut FinishedFile<'_>) -> Result<(), Error>;
+}
+
+#[cfg(any(feature = "hooks-archive", feature = "hooks-image"))]
+fn failed(path: &Path, e: impl std::fmt::Display) -> Error {
+    Error::HookFailed(format!("{}: {}", path.display(), e))
+}
+
+#[cfg(feature = "hooks-archive")]
+// `name` with `suffix` removed, compared case-insensitively
+fn strip_suffix(path: &Path, suffix: &str) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    if name.len() <= suffix.len() || !name.to_ascii_lowercase().ends_with(suffix) {
+        return None;
+    }
+    Some(path.with_file_name(&name[..name.len() - suffix.len()]))
+}
+
+// decompress `.gz` and `.zst` files next to themselves, `a.json.gz` becomes `a.json`
+#[cfg(feature = "hooks-archive")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Decompress {
+    pub keep_original: bool,
+}
+
+#[cfg(feature = "hooks-archive")]
+impl Hook for Decompress {
+    fn finished(&self, file: &mut FinishedFile<'_>) -> Result<(), Error> {
+        let path = &file.path;
+        let (output, gzip) = match (strip_suffix(path, ".gz"), strip_suffix(path, ".zst")) {
+            (Some(output), _) => (output, true),
+            (None, Some(output)) => (output, false),
+            (None, None) => return Ok(()),
+        };
+        let storage = file.storage;
+        let input = BufReader::new(storage.open(path).map_err(|e| failed(path, e))?);
+        let mut decoder: Box<dyn Read> = if gzip {
+            Box::new(flate2::read::MultiGzDecoder::new(input))
+        } else {
+            Box::new(zstd::Decoder::with_buffer(input).map_err(|e| failed(path, e))?)
+        };
+        let written = storage.create(&output).and_then(|mut out| {
+            io::copy(&mut decoder, &mut out)?;
+            out.flush()
+        });
+        if let Err(e) = written {
+            let _ = storage.remove(&output);
+            return Err(failed(path, e));
+        }
+        if !self.keep_original {
+            storage.remove(path).map_err(|e| failed(path, e))?;
+        }
+        file.path = output;
+        Ok(())
+    }
+}
+
+// extract zip and tar archives into a folder named after them, `a.zip` into `a/`
+#[cfg(feature = "hooks-archive")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Extract {
+    pub keep_archive: bool,
+}
+
+#[cfg(feature = "hooks-archive")]
+impl Hook for Extract {
+    fn finished(&self, file: &mut FinishedFile<'_>) -> Result<(), Error> {
+        let path = &file.path;
+        let suffixes = [".zip", ".tar", ".tar.gz", ".tgz", ".tar.zst", ".tzst"];
+        let Some((suffix, folder)) = suffixes
+            .iter()
+            .rev()
+            .find_map(|suffix| strip_suffix(path, suffix).map(|folder| (*suffix, folder)))
+        else {
+            return Ok(());
+        };
+        let storage = file.storage;
+        // the central directory of a zip is at the end, it is read in place
+        let extracted = if suffix == ".zip" {
+            storage
+                .open_seekable(path)
+                .and_then(|input| unzip(storage, BufReader::new(input), &folder))
+        } else {
+            storage
+                .open(path)
+                .map(BufReader::new)
+                .and_then(|input| match suffix {
+                    ".tar" => untar(storage, input, &folder),
+                    ".tar.gz" | ".tgz" => {
+                        untar(storage, flate2::read::MultiGzDecoder::new(input), &folder)
+                    }
+                    _ => zstd::Decoder::with_buffer(input)
+                        .and_then(|decoder| untar(storage, decoder, &folder)),
+                })
+        };
+        extracted.map_err(|e| failed(path, e))?;
+        if !self.keep_archive {
+            storage.remove(path).map_err(|e| failed(path, e))?;
+            file.path = folder;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "hooks-archive")]
+// `name` inside `folder`, `None` when it would escape it
+fn enclosed(folder: &Path, name: &Path) -> Option<PathBuf> {
+    let mut path = folder.to_path_buf();
+    for component in name.components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    Some(path)
+}
+
+#[cfg(feature = "hooks-archive")]
+fn escaping(name: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{} escapes the folder", name.display()),
+    )
+}
+
+#[cfg(feature = "hooks-archive")]
+// write `entry` to `path`, with the folders above it
+fn store(storage: &dyn Storage, path: &Path, entry: &mut impl Read) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        storage.create_dir_all(parent)?;
+    }
+    let mut out = storage.create(path)?;
+    io::copy(entry, &mut out)?;
+    out.flush()
+}
+
+#[cfg(feature = "hooks-archive")]
+// folders and regular files, links and special files are left out
+fn untar(storage: &dyn Storage, input: impl Read, folder: &Path) -> io::Result<()> {
+    storage.create_dir_all(folder)?;
+    for entry in tar::Archive::new(input).entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.into_owned();
+        let path = enclosed(folder, &name).ok_or_else(|| escaping(&name))?;
+        match entry.header().entry_type() {
+            tar::EntryType::Directory => storage.create_dir_all(&path)?,
+            tar::EntryType::Regular | tar::EntryType::Continuous => {
+                store(storage, &path, &mut entry)?
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "hooks-archive")]
+fn unzip(storage: &dyn Storage, input: impl Read + Seek, folder: &Path) -> io::Result<()> {
+    let mut zip = zip::ZipArchive::new(input)?;
+    storage.create_dir_all(folder)?;
+    for index in 0..zip.len() {
+        let mut entry = zip.by_index(index)?;
+        let name = PathBuf::from(entry.name());
+        let path = enclosed(folder, &name).ok_or_else(|| escaping(&name))?;
+        if entry.is_dir() {
+            storage.create_dir_all(&path)?;
+        } else {
+            store(storage, &path, &mut entry)?;
+        }
+    }
+    Ok(())
+}
+
+// fail BMP, GIF, JPEG, PNG and WebP files that do not decode, by `Content-Type` or extension
+#[cfg(feature = "hooks-image")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VerifyImage;
+
+#[cfg(feature = "hooks-image")]
+impl Hook for VerifyImage {
+    fn finished(&self, file: &mut FinishedFile<'_>) -> Result<(), Error> {
+        let path = &file.path;
+        let format = file
+            .headers
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| image::ImageFormat::from_mime_type(v.split(';').next().unwrap_or(v)))
+            .or_else(|| image::ImageFormat::from_path(path).ok());
+        let Some(format) = format else {
+            return Ok(());
+        };
+        let is_file = file.storage.metadata(path).is_some_and(|m| !m.is_dir);
+        if !format.reading_enabled() || !is_file {
+            return Ok(());
+        }
+        let mut content = vec![];
+        file.storage
+            .open(path)
+            .and_then(|mut reader| reader.read_to_end(&mut content))
+            .map_err(|e| failed(path, e))?;
+        image::load_from_memory_with_format(&content, format)
+            .map(drop)
+            .map_err(|e| failed(path, format!("Image Undecodable: {}", e)))
+    }
+}
@@ -0,0 +1,517 @@
+#[cfg(test)]
+#[allow(clippy::items_after_test_module)]
+mod tests {
+    #[cfg(feature = "csv")]
+    use super::read_csv;
+    use super::{parse_aria2, parse_metalink, read_jsonl, ColumnMap};
+    use crate::{Error, HashAlgorithm};
+    use std::path::PathBuf;
+
+    #[test]
+    fn aria2() {
+        let input = "# comment
+https://a.example/f.iso\thttps://b.example/f.iso
+  dir=/tmp/iso
+  out=sub/file.iso
+  header=Referer: https://example.com
+  checksum=sha-1=a9993e364706816aba3e25717850c26c9cd0d89d
+  max-connection-per-server=4
+
+https://c.example/g.bin
+";
+        let tasks = parse_aria2(input.as_bytes()).unwrap();
+        assert_eq!(tasks.len(), 2);
+        assert_eq!(tasks[0].mirrors, vec!["https://b.example/f.iso"]);
+        assert_eq!(tasks[0].path, PathBuf::from("/tmp/iso/sub"));
+        assert_eq!(tasks[0].filename.as_deref(), Some("file.iso"));
+        assert_eq!(tasks[0].headers["referer"], "https://example.com");
+        assert!(tasks[0].checksum.as_ref().unwrap().verify(b"abc"));
+        assert_eq!(tasks[1].path, PathBuf::from("."));
+        assert!(tasks[1].filename.is_none());
+
+        assert!(parse_aria2("  out=x\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn metalink() {
+        let v4 = r#"<?xml version="1.0" encoding="UTF-8"?>
+<metalink xmlns="urn:ietf:params:xml:ns:metalink">
+  <file name="iso/example.iso">
+    <size>14471447</size>
+    <hash type="sha-1">a9993e364706816aba3e25717850c26c9cd0d89d</hash>
+    <hash type="sha-256">ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad</hash>
+    <pieces length="262144" type="sha-512">
+      <hash>0000</hash>
+    </pieces>
+    <url location="de" priority="2">https://b.example.com/example.iso</url>
+    <url priority="1">https://a.example.com/example.iso</url>
+    <metaurl mediatype="torrent">https://a.example.com/example.torrent</metaurl>
+  </file>
+</metalink>"#;
+        let tasks = parse_metalink(v4).unwrap();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].url, "https://a.example.com/example.iso");
+        assert_eq!(tasks[0].mirrors, vec!["https://b.example.com/example.iso"]);
+        assert_eq!(tasks[0].path, PathBuf::from("iso"));
+        assert_eq!(tasks[0].filename.as_deref(), Some("example.iso"));
+        assert_eq!(tasks[0].size, Some(14471447));
+        let checksum = tasks[0].checksum.as_ref().unwrap();
+        assert_eq!(checksum.algorithm, HashAlgorithm::Sha256);
+        assert!(checksum.verify(b"abc"));
+
+        let v3 = r#"<metalink version="3.0" xmlns="http://www.metalinker.org/"><files>
+  <file name="a.bin"><verification><hash type="md5">900150983cd24fb0d6963f7d28e17f72</hash></verification>
+    <resources>
+      <url type="http" preference="10">http://slow.example.com/a.bin</url>
+      <url type="bittorrent" preference="100">http://a.example.com/a.torrent.bin</url>
+      <url type="http" preference="90">http://fast.example.com/a.bin</url>
+    </resources></file></files></metalink>"#;
+        let tasks = parse_metalink(v3).unwrap();
+        assert_eq!(tasks[0].url, "http://fast.example.com/a.bin");
+        assert_eq!(tasks[0].mirrors, vec!["http://slow.example.com/a.bin"]);
+        assert!(parse_metalink(r#"<file name="../x"><url>http://a/x</url></file>"#).is_err());
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn csv() {
+        let input = "link,dir,auth\n\
+            https://a.example/1.bin,x,\"Authorization: Bearer t\"\n\
+            https://a.example/2.bin,,\n";
+        let columns = ColumnMap::parse("url=link,folder=dir,headers=auth").unwrap();
+        let tasks: Vec<_> = read_csv(input.as_bytes(), b',', true, &columns)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(tasks.len(), 2);
+        assert_eq!(tasks[0].path, PathBuf::from("x"));
+        assert_eq!(tasks[0].headers["authorization"], "Bearer t");
+        assert_eq!(tasks[1].path, PathBuf::from("."));
+
+        let columns = ColumnMap::parse("url=1,filename=0").unwrap();
+        let mut tasks = read_csv(
+            "a.bin\thttps://a.example/\n".as_bytes(),
+            b'\t',
+            false,
+            &columns,
+        )
+        .unwrap();
+        assert_eq!(
+            tasks.next().unwrap().unwrap().filename.as_deref(),
+            Some("a.bin")
+        );
+        assert!(read_csv("x\n".as_bytes(), b',', true, &ColumnMap::default()).is_err());
+    }
+
+    #[test]
+    fn jsonl() {
+        let input = r#"{"url": "https://a.example/1", "headers": {"Referer": "https://b"}}
+
+{"url": "https://a.example/2", "hash": "md5=5d41402abc4b2a76b9719d911017c592"}
+{"folder": "x"}"#;
+        let tasks: Vec<_> = read_jsonl(input.as_bytes(), &ColumnMap::default()).collect();
+        assert_eq!(tasks.len(), 3);
+        assert_eq!(tasks[0].as_ref().unwrap().headers["referer"], "https://b");
+        assert!(tasks[1].as_ref().unwrap().checksum.is_some());
+        assert!(matches!(&tasks[2], Err(Error::InputIllegal(e)) if e.starts_with("line 4")));
+    }
+}
+
+use crate::markup;
+use crate::{Checksum, DownloadTask, Error, HashAlgorithm};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde_json::Value;
+use std::io::BufRead;
+#[cfg(feature = "csv")]
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+// aria2 input file (`aria2c -i`), only the `dir`, `out`, `header` and `checksum` options
+pub fn parse_aria2<R: BufRead>(reader: R) -> Result<Vec<DownloadTask>, Error> {
+    // `dir` and `out` may come in any order, so they are combined at the end
+    let mut entries: Vec<(DownloadTask, PathBuf, Option<PathBuf>)> = vec![];
+    for (number, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| Error::IoError(e.to_string()))?;
+        let illegal =
+            |reason: &str| Error::InputIllegal(format!("line {}: {}", number + 1, reason));
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        if !line.starts_with(char::is_whitespace) {
+            let mut uris = line
+                .split('\t')
+                .map(str::trim)
+                .filter(|uri| !uri.is_empty());
+            let url = uris.next().unwrap().to_string();
+            let mut task = DownloadTask::new(url, PathBuf::new(), None);
+            task.mirrors = uris.map(str::to_string).collect();
+            entries.push((task, PathBuf::from("."), None));
+            continue;
+        }
+        let (task, dir, out) = entries
+            .last_mut()
+            .ok_or_else(|| illegal("option before any uri"))?;
+        let (key, value) = line
+            .trim()
+            .split_once('=')
+            .ok_or_else(|| illegal("option is not `key=value`"))?;
+        match key.trim() {
+            "dir" => *dir = PathBuf::from(value),
+            "out" => *out = Some(PathBuf::from(value)),
+            "header" => append_header(&mut task.headers, value).map_err(illegal)?,
+            "checksum" => task.checksum = Some(Checksum::parse(value)?),
+            _ => {}
+        }
+    }
+    Ok(entries
+        .into_iter()
+        .map(|(mut task, dir, out)| {
+            // `out` may contain folders relative to `dir`
+            task.path = match out.as_ref().and_then(|out| out.parent()) {
+                Some(parent) => dir.join(parent),
+                None => dir,
+            };
+            task.filename = out
+                .as_ref()
+                .and_then(|out| out.file_name())
+                .map(|name| name.to_string_lossy().to_string());
+            task
+        })
+        .collect())
+}
+
+fn append_header(headers: &mut HeaderMap, header: &str) -> Result<(), &'static str> {
+    let (name, value) = header
+        .split_once(':')
+        .ok_or("header is not `name: value`")?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes()).map_err(|_| "illegal header name")?;
+    let value = HeaderValue::from_str(value.trim()).map_err(|_| "illegal header value")?;
+    headers.append(name, value);
+    Ok(())
+}
+
+// `type=digest`, a bare digest is blake3 like the hash check of existing files
+fn parse_hash(hash: &str) -> Result<Checksum, Error> {
+    if hash.contains('=') {
+        Checksum::parse(hash)
+    } else {
+        Checksum::new(HashAlgorithm::Blake3, hash)
+    }
+}
+
+// columns of a CSV file or fields of a JSON Lines object, by name or zero-based position
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnMap {
+    pub url: String,
+    pub folder: Option<String>,
+    pub filename: Option<String>,
+    // expected checksum, `type=digest` or a bare blake3 digest
+    pub hash: Option<String>,
+    // each holds `name: value` headers, one per line
+    pub headers: Vec<String>,
+}
+
+impl Default for ColumnMap {
+    fn default() -> Self {
+        Self {
+            url: "url".to_string(),
+            folder: Some("folder".to_string()),
+            filename: Some("filename".to_string()),
+            hash: Some("hash".to_string()),
+            headers: vec!["headers".to_string()],
+        }
+    }
+}
+
+impl ColumnMap {
+    // override the defaults with `field=column` pairs, e.g. `url=link,folder=2,headers=auth`
+    pub fn parse(spec: &str) -> Result<Self, Error> {
+        let mut map = Self::default();
+        let mut headers = vec![];
+        for pair in spec.split(',').filter(|p| !p.trim().is_empty()) {
+            let (field, column) = pair
+                .split_once('=')
+                .ok_or_else(|| Error::InputIllegal(format!("column map `{}`", pair)))?;
+            let column = column.trim().to_string();
+            match field.trim() {
+                "url" => map.url = column,
+                "folder" => map.folder = Some(column),
+                "filename" => map.filename = Some(column),
+                "hash" => map.hash = Some(column),
+                "headers" => headers.push(column),
+                field => {
+                    return Err(Error::InputIllegal(format!(
+                        "unknown field `{}` in column map",
+                        field
+                    )))
+                }
+            }
+        }
+        if !headers.is_empty() {
+            map.headers = headers;
+        }
+        Ok(map)
+    }
+}
+
+// positions of the mapped columns in a record
+#[cfg(feature = "csv")]
+struct ResolvedColumns {
+    url: usize,
+    folder: Option<usize>,
+    filename: Option<usize>,
+    hash: Option<usize>,
+    headers: Vec<usize>,
+}
+
+#[cfg(feature = "csv")]
+impl ResolvedColumns {
+    fn new(map: &ColumnMap, names: Option<&csv::StringRecord>) -> Result<Self, Error> {
+        let find = |column: &str| match column.parse::<usize>() {
+            Ok(index) => Some(index),
+            Err(_) => names.and_then(|names| names.iter().position(|n| n.trim() == column)),
+        };
+        Ok(Self {
+            url: find(&map.url).ok_or_else(|| {
+                Error::InputIllegal(format!("column `{}` of url not found", map.url))
+            })?,
+            folder: map.folder.as_deref().and_then(find),
+            filename: map.filename.as_deref().and_then(find),
+            hash: map.hash.as_deref().and_then(find),
+            headers: map.headers.iter().filter_map(|h| find(h)).collect(),
+        })
+    }
+
+    fn task(&self, record: &csv::StringRecord) -> Result<DownloadTask, String> {
+        let get = |index: Option<usize>| {
+            index
+                .and_then(|i| record.get(i))
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+        };
+        let url = get(Some(self.url)).ok_or("empty url")?;
+        let mut task = DownloadTask::new(
+            url.to_string(),
+            PathBuf::from(get(self.folder).unwrap_or(".")),
+            get(self.filename).map(str::to_string),
+        );
+        if let Some(hash) = get(self.hash) {
+            task.checksum = Some(parse_hash(hash).map_err(|e| e.to_string())?);
+        }
+        for &column in &self.headers {
+            for header in get(Some(column)).unwrap_or("").lines() {
+                append_header(&mut task.headers, header)?;
+            }
+        }
+        Ok(task)
+    }
+}
+
+// stream tasks from CSV, or TSV with `delimiter` set to `b'\t'`
+#[cfg(feature = "csv")]
+pub fn read_csv<R: Read>(
+    reader: R,
+    delimiter: u8,
+    has_headers: bool,
+    columns: &ColumnMap,
+) -> Result<impl Iterator<Item = Result<DownloadTask, Error>>, Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(has_headers)
+        .flexible(true)
+        .from_reader(reader);
+    let names = if has_headers {
+        Some(
+            reader
+                .headers()
+                .map_err(|e| Error::InputIllegal(e.to_string()))?
+                .clone(),
+        )
+    } else {
+        None
+    };
+    let columns = ResolvedColumns::new(columns, names.as_ref())?;
+    Ok(reader.into_records().map(move |record| {
+        let record = record.map_err(|e| Error::InputIllegal(e.to_string()))?;
+        let line = record.position().map_or(0, |p| p.line());
+        columns
+            .task(&record)
+            .map_err(|e| Error::InputIllegal(format!("line {}: {}", line, e)))
+    }))
+}
+
+// stream tasks from JSON Lines, blank lines are skipped
+pub fn read_jsonl<R: BufRead>(
+    reader: R,
+    columns: &ColumnMap,
+) -> impl Iterator<Item = Result<DownloadTask, Error>> {
+    let columns = columns.clone();
+    reader
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.as_ref().map_or(true, |l| !l.trim().is_empty()))
+        .map(move |(number, line)| {
+            let line = line.map_err(|e| Error::IoError(e.to_string()))?;
+            json_task(&line, &columns)
+                .map_err(|e| Error::InputIllegal(format!("line {}: {}", number + 1, e)))
+        })
+}
+
+fn json_task(line: &str, columns: &ColumnMap) -> Result<DownloadTask, String> {
+    let object: Value = serde_json::from_str(line).map_err(|e| e.to_string())?;
+    let get = |field: Option<&String>| {
+        field
+            .and_then(|f| object.get(f))
+            .and_then(Value::as_str)
+            .filter(|v| !v.is_empty())
+    };
+    let url = get(Some(&columns.url)).ok_or("no url")?;
+    let mut task = DownloadTask::new(
+        url.to_string(),
+        PathBuf::from(get(columns.folder.as_ref()).unwrap_or(".")),
+        get(columns.filename.as_ref()).map(str::to_string),
+    );
+    if let Some(hash) = get(columns.hash.as_ref()) {
+        task.checksum = Some(parse_hash(hash).map_err(|e| e.to_string())?);
+    }
+    for field in &columns.headers {
+        match object.get(field) {
+            Some(Value::Object(headers)) => {
+                for (name, value) in headers {
+                    let value = value.as_str().ok_or("header value is not a string")?;
+                    append_header(&mut task.headers, &format!("{}: {}", name, value))?;
+                }
+            }
+            Some(Value::Array(headers)) => {
+                for header in headers {
+                    append_header(
+                        &mut task.headers,
+                        header.as_str().ok_or("header is not a string")?,
+                    )?;
+                }
+            }
+            Some(Value::String(header)) => append_header(&mut task.headers, header)?,
+            Some(Value::Null) | None => {}
+            Some(_) => return Err("illegal headers".to_string()),
+        }
+    }
+    Ok(task)
+}
+
+// preferred digest when a metalink lists several
+fn strength(algorithm: HashAlgorithm) -> u8 {
+    match algorithm {
+        HashAlgorithm::Md5 => 0,
+        HashAlgorithm::Sha1 => 1,
+        HashAlgorithm::Sha224 => 2,
+        HashAlgorithm::Sha256 => 3,
+        HashAlgorithm::Sha384 => 4,
+        HashAlgorithm::Sha512 => 5,
+        HashAlgorithm::Blake3 => 6,
+    }
+}
+
+#[derive(Default)]
+struct MetalinkFile {
+    name: String,
+    size: Option<u64>,
+    checksum: Option<Checksum>,
+    // rank and url, lower ranks are tried first
+    urls: Vec<(i64, String)>,
+}
+
+impl MetalinkFile {
+    fn into_task(mut self) -> Result<DownloadTask, Error> {
+        let illegal = |reason: &str| Error::InputIllegal(format!("{}: {}", reason, self.name));
+        // the name may have folders, but must stay inside the destination
+        let name = Path::new(&self.name);
+        if !name.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(illegal("unsafe file name"));
+        }
+        let filename = name
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .ok_or_else(|| illegal("empty file name"))?;
+        let path = name.parent().map_or_else(PathBuf::new, Path::to_path_buf);
+        // stable, so urls of the same rank keep the order of the document
+        self.urls.sort_by_key(|(rank, _)| *rank);
+        let mut urls = self.urls.into_iter().map(|(_, url)| url);
+        let url = urls.next().ok_or_else(|| illegal("no usable url"))?;
+        let mut task = DownloadTask::new(url, path, Some(filename));
+        task.mirrors = urls.collect();
+        task.size = self.size;
+        task.checksum = self.checksum;
+        Ok(task)
+    }
+}
+
+// Metalink 4 (`.meta4`) or 3 (`.metalink`), one task per `<file>` with its urls as mirrors
+pub fn parse_metalink(document: &str) -> Result<Vec<DownloadTask>, Error> {
+    let mut tasks = vec![];
+    let mut file: Option<MetalinkFile> = None;
+    let mut in_pieces = false;
+    for tag in markup::tags(document) {
+        match (tag.name.as_str(), tag.closing) {
+            ("file", false) => {
+                file = Some(MetalinkFile {
+                    name: tag.attribute("name").unwrap_or_default().to_string(),
+                    ..Default::default()
+                });
+            }
+            ("file", true) => {
+                if let Some(file) = file.take() {
+                    tasks.push(file.into_task()?);
+                }
+            }
+            ("pieces", closing) => in_pieces = !closing && !tag.self_closing,
+            _ => {}
+        }
+        let file = match file.as_mut() {
+            Some(file) if !tag.closing => file,
+            _ => continue,
+        };
+        match tag.name.as_str() {
+            "size" => {
+                let size = tag.text();
+                file.size = Some(
+                    size.parse()
+                        .map_err(|_| Error::InputIllegal(format!("size `{}`", size)))?,
+                );
+            }
+            "hash" if !in_pieces => {
+                let algorithm = tag.attribute("type").and_then(HashAlgorithm::parse);
+                if let Some(algorithm) = algorithm {
+                    let checksum = Checksum::new(algorithm, &tag.text())?;
+                    if file
+                        .checksum
+                        .as_ref()
+                        .is_none_or(|c| strength(c.algorithm) < strength(algorithm))
+                    {
+                        file.checksum = Some(checksum);
+                    }
+                }
+            }
+            "url" => {
+                let url = tag.text();
+                // v3 also lists torrents and such by `type`
+                let supported = reqwest::Url::parse(&url)
+                    .is_ok_and(|u| matches!(u.scheme(), "http" | "https" | "ftp"))
+                    && tag
+                        .attribute("type")
+                        .is_none_or(|t| matches!(t, "http" | "https" | "ftp"));
+                if !supported {
+                    continue;
+                }
+                let rank = match (tag.attribute("priority"), tag.attribute("preference")) {
+                    (Some(priority), _) => priority.parse().unwrap_or(i64::MAX),
+                    // higher preference is better
+                    (None, Some(preference)) => preference.parse().map_or(i64::MAX, |p: i64| -p),
+                    (None, None) => i64::MAX,
+                };
+                file.urls.push((rank, url));
+            }
+            _ => {}
+        }
+    }
+    Ok(tasks)
+}
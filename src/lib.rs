@@ -3,6 +3,7 @@
 mod tests {
     use crate::Downloader;
     use reqwest::Url;
+    use std::io::{Read, Write};
     use std::path::{Path, PathBuf};
 
     #[test]
@@ -11,87 +12,306 @@ mod tests {
         let mut dler = Downloader::new();
         dler.set_destination(PathBuf::from("."));
         dler.set_hash_check(true);
-        dler.append_task(
+        dler.append_task((
             "https://avatars.githubusercontent.com/u/6939913?s=48&v=4".to_string(),
             PathBuf::from("."),
             None,
-        );
+        ));
         let result = dler.download(|_, _, _, _| {}).unwrap();
         for r in result {
             println!("Failed: {}, due to {:?}", r.url, r.err);
         }
     }
+
+    #[test]
+    fn streamed_tasks() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for socket in listener.incoming() {
+                let mut socket = socket.unwrap();
+                let _ = socket.read(&mut [0; 1024]).unwrap();
+                socket
+                    .write_all(
+                        b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok",
+                    )
+                    .unwrap();
+            }
+        });
+        let dir = std::env::temp_dir().join(format!("shirodl-stream-{}", std::process::id()));
+        let mut dler = Downloader::new();
+        dler.set_destination(dir.clone());
+        dler.set_task_count(1);
+        let sender = dler.task_channel(1);
+        let producer = std::thread::spawn(move || {
+            for name in ["a", "b", "c", "a"] {
+                let task = (url.clone(), PathBuf::from("."), Some(name.to_string()));
+                sender.blocking_send(task).unwrap();
+            }
+        });
+        let reports = dler.download_report(|_, _, _, _| {}).unwrap();
+        producer.join().unwrap();
+        assert_eq!(reports.len(), 3);
+        assert!(reports.iter().all(|r| r.result.is_ok()));
+        assert_eq!(std::fs::read(dir.join("c")).unwrap(), b"ok");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
 
-use blake3::Hasher;
+#[cfg(feature = "archive")]
+mod archive;
+mod cache;
+mod checksum;
+mod conflict;
+mod date;
+mod dedupe;
+mod dns;
+#[cfg(feature = "hls")]
+mod hls;
+mod hook;
+pub mod input;
+#[cfg(feature = "links")]
+mod links;
+mod manifest;
+mod markup;
+mod origin;
+mod pattern;
+mod proxy;
+mod quota;
+mod redirect;
+mod scheme;
+mod sink;
+mod storage;
+mod template;
+mod transfer;
+mod verify;
+
+#[cfg(feature = "archive")]
+pub use archive::ArchiveFormat;
+pub use checksum::{Checksum, HashAlgorithm};
+pub use conflict::ConflictPolicy;
+pub use dns::IpFamily;
+#[cfg(feature = "hls")]
+pub use hls::HlsVariant;
+#[cfg(feature = "hooks-image")]
+pub use hook::VerifyImage;
+#[cfg(feature = "hooks-archive")]
+pub use hook::{Decompress, Extract};
+pub use hook::{FinishedFile, Hook};
+#[cfg(feature = "links")]
+pub use links::LinkFilter;
+pub use manifest::ManifestFormat;
+pub use origin::OriginMetadata;
+pub use pattern::UrlPattern;
+pub use proxy::{ProxyStrategy, ProxyType};
+pub use reqwest::tls::Version as TlsVersion;
+pub use sink::TaskSink;
+pub use storage::{LocalStorage, Metadata, ReadSeek, Storage};
+pub use template::PathTemplate;
+pub use verify::{Verification, VerifyReport};
+
 // use content_inspector;
-use reqwest::header::{HeaderMap, HeaderValue, IntoHeaderName};
-use reqwest::{Client, Error as HttpError, Proxy, Url};
+#[cfg(feature = "archive")]
+use archive::ArchiveWriter;
+use cache::Cache;
+use conflict::Existing;
+use dedupe::Dedupe;
+use manifest::Manifest;
+use proxy::ProxyPool;
+use quota::Budget;
+use redirect::RedirectPolicy;
+use reqwest::header::{HeaderMap, HeaderValue, IntoHeaderName, IF_MODIFIED_SINCE};
+use reqwest::redirect::Policy;
+use reqwest::tls::{Certificate, Identity};
+use reqwest::{Client, Error as HttpError, Proxy, StatusCode, Url};
+use scheme::Connection;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use tokio::sync::Semaphore;
+use tokio::sync::{mpsc, Semaphore};
+use transfer::SpeedLimits;
 
-struct DownloadParams {
-    url: String,
-    path: PathBuf, // relative to folder of Downloader
-    filename: Option<String>,
+#[derive(Clone)]
+struct WorkerOptions {
+    conflict: ConflictPolicy,
+    only_binary: bool,
+    auto_rename: bool,
+    redirect: RedirectPolicy,
+    filename_from_final_url: bool,
+    speed: SpeedLimits,
+    connection: Connection,
+    headers: HeaderMap,
+    checksum: Option<Checksum>,
+    size: Option<u64>,
+    storage: Arc<dyn Storage>,
+    #[cfg(feature = "archive")]
+    archive: Option<Arc<ArchiveWriter>>,
+    cache: Option<Arc<Cache>>,
+    mtime: bool,
+    origin: Option<OriginMetadata>,
+    sink: TaskSink,
+    // content saved for a sink other than a file
+    delivered: Arc<Mutex<Option<Vec<u8>>>>,
+    hooks: Arc<Vec<Box<dyn Hook>>>,
+    manifest: Option<Arc<Manifest>>,
+    budget: Option<Arc<Budget>>,
 }
 
-pub enum ProxyType {
-    Http,
-    Https,
-    All,
+impl WorkerOptions {
+    // nothing is written to the destination in archive mode
+    #[cfg(feature = "archive")]
+    fn archived(&self) -> bool {
+        self.archive.is_some()
+    }
+
+    #[cfg(not(feature = "archive"))]
+    fn archived(&self) -> bool {
+        false
+    }
 }
 
 pub struct Downloader {
-    list: Vec<DownloadParams>,
+    list: Vec<DownloadTask>,
+    queued: Dedupe,
+    receiver: Option<mpsc::Receiver<DownloadTask>>,
+    stream_window: usize,
     folder: PathBuf,
+    template: Option<PathTemplate>,
+    mirror: bool,
     timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    speed: SpeedLimits,
     headers: HeaderMap,
-    hash_check: bool,
+    conflict: ConflictPolicy,
     only_binary: bool,
     auto_rename: bool,
+    redirect: RedirectPolicy,
+    filename_from_final_url: bool,
     proxies: Vec<Proxy>,
+    proxy_strategy: ProxyStrategy,
+    proxy_failure_threshold: usize,
+    proxy_cooldown: Duration,
+    root_certificates: Vec<Certificate>,
+    identity: Option<Identity>,
+    min_tls_version: Option<TlsVersion>,
+    accept_invalid_certs: bool,
+    dns_overrides: HashMap<String, Vec<IpAddr>>,
+    ip_family: IpFamily,
+    local_address: Option<IpAddr>,
     task_count: usize,
     disable_default_proxy: bool,
     retries: usize,
+    storage: Arc<dyn Storage>,
+    #[cfg(feature = "archive")]
+    archive: Option<(PathBuf, ArchiveFormat)>,
+    cache: Option<PathBuf>,
+    mtime: bool,
+    origin: Option<OriginMetadata>,
+    hooks: Vec<Box<dyn Hook>>,
+    manifests: Vec<(PathBuf, ManifestFormat)>,
+    quota: Option<u64>,
+    min_free_space: Option<u64>,
 }
 
 pub struct DownloadFailed {
     pub url: String,
     pub path: PathBuf,
     pub filename: Option<String>,
+    pub redirects: Vec<String>,
     pub err: Error,
 }
 
-pub struct DownloadTask {
+// outcome of a single task, returned by `Downloader::download_report`
+pub struct DownloadReport {
     pub url: String,
     pub path: PathBuf,
     pub filename: Option<String>,
+    // every url the request was redirected to, the last one is the final url
+    pub redirects: Vec<String>,
+    pub result: Result<(), Error>,
+    // the downloaded bytes for `TaskSink::Memory`
+    pub content: Option<Vec<u8>>,
 }
 
-impl Into<DownloadTask> for DownloadFailed {
-    fn into(self) -> DownloadTask {
-        DownloadTask {
-            url: self.url,
-            path: self.path,
-            filename: self.filename,
-        }
+impl DownloadReport {
+    pub fn final_url(&self) -> &str {
+        self.redirects.last().unwrap_or(&self.url)
     }
 }
 
-impl Into<DownloadTask> for (String, PathBuf, Option<String>) {
-    fn into(self) -> DownloadTask {
+#[derive(Debug, Clone)]
+pub struct DownloadTask {
+    pub url: String,
+    pub path: PathBuf, // relative to folder of Downloader
+    pub filename: Option<String>,
+    // tried in turn when the url fails
+    pub mirrors: Vec<String>,
+    // sent in addition to the headers of the Downloader
+    pub headers: HeaderMap,
+    pub checksum: Option<Checksum>,
+    // expected length of the body
+    pub size: Option<u64>,
+    // overrides the template of the Downloader, only used without `filename`
+    pub template: Option<PathTemplate>,
+    // overrides the conflict policy of the Downloader
+    pub conflict: Option<ConflictPolicy>,
+    // the url is an HLS playlist, its segments are saved as one `.ts` file
+    #[cfg(feature = "hls")]
+    pub hls: Option<HlsVariant>,
+    pub sink: TaskSink,
+}
+
+impl DownloadTask {
+    pub fn new(url: String, path: PathBuf, filename: Option<String>) -> Self {
         DownloadTask {
-            url: self.0,
-            path: self.1,
-            filename: self.2,
+            url,
+            path,
+            filename,
+            mirrors: vec![],
+            headers: HeaderMap::new(),
+            checksum: None,
+            size: None,
+            template: None,
+            conflict: None,
+            #[cfg(feature = "hls")]
+            hls: None,
+            sink: TaskSink::File,
         }
     }
 }
 
+impl From<DownloadFailed> for DownloadTask {
+    fn from(failed: DownloadFailed) -> Self {
+        DownloadTask::new(failed.url, failed.path, failed.filename)
+    }
+}
+
+impl From<(String, PathBuf, Option<String>)> for DownloadTask {
+    fn from(task: (String, PathBuf, Option<String>)) -> Self {
+        DownloadTask::new(task.0, task.1, task.2)
+    }
+}
+
+// submits tasks to a running `Downloader`, see `Downloader::task_channel`
+#[derive(Clone)]
+pub struct TaskSender(mpsc::Sender<DownloadTask>);
+
+impl TaskSender {
+    // waits while the channel is full, gives the task back when the batch is gone
+    pub async fn send<T: Into<DownloadTask>>(&self, task: T) -> Result<(), Box<DownloadTask>> {
+        self.0.send(task.into()).await.map_err(|e| Box::new(e.0))
+    }
+
+    // for threads outside of any async runtime, panics when called inside one
+    pub fn blocking_send<T: Into<DownloadTask>>(&self, task: T) -> Result<(), Box<DownloadTask>> {
+        self.0.blocking_send(task.into()).map_err(|e| Box::new(e.0))
+    }
+}
+
 #[derive(Debug)]
 pub enum Error {
     FileExisted,
@@ -113,32 +333,75 @@ pub enum Error {
     HashingError,
     HashingErrorWhenRename,
     ProxyError(String),
+    TlsError(String),
+    TooManyRedirects(usize),
+    RedirectToOtherHost(String),
+    ConnectTimeout,
+    ReadIdleTimeout(Duration),
+    LowSpeed(u64, Duration),
+    ResolveOverrideIllegal(String),
+    TemplateIllegal(String),
+    PatternIllegal(String),
+    InputIllegal(String),
+    ChecksumIllegal(String),
+    ChecksumMismatch(HashAlgorithm),
+    PlaylistIllegal(String),
+    EncryptionUnsupported(String),
+    DecryptionFailed,
+    // expected and received length
+    SizeMismatch(u64, u64),
+    // reply code, 0 for a broken conversation, and reply text
+    FtpError(u16, String),
+    HookFailed(String),
+    FileMissing,
+    // the batch wrote its quota of bytes
+    QuotaExceeded(u64),
+    // bytes needed and available on the destination
+    InsufficientSpace(u64, u64),
 }
 
 impl Error {
     pub fn ignorable(&self) -> bool {
+        matches!(
+            self,
+            Self::DifferentFileExistedWhenRename
+                | Self::FileExistedAsFolderWhenRename
+                | Self::HashingErrorWhenRename
+                | Self::IoErrorWhenRename(_)
+                | Self::UrlCannotDownload
+                | Self::FileIsNotBinary
+                | Self::FileExisted
+        )
+    }
+
+    pub fn retriable(&self) -> bool {
         match self {
-            Self::DifferentFileExistedWhenRename => true,
-            Self::FileExistedAsFolderWhenRename => true,
-            Self::HashingErrorWhenRename => true,
-            Self::IoErrorWhenRename(_) => true,
-            Self::UrlCannotDownload => true,
-            Self::FileIsNotBinary => true,
-            Self::FileExisted => true,
+            Self::HttpError(http_error) => http_error.is_connect() || http_error.is_timeout(),
+            Self::ProxyError(_) => true,
+            Self::ConnectTimeout | Self::ReadIdleTimeout(_) | Self::LowSpeed(..) => true,
+            // corrupted on the way
+            Self::ChecksumMismatch(_) | Self::SizeMismatch(..) => true,
+            // transient negative completion
+            Self::FtpError(code, _) => (400..500).contains(code),
             _ => false,
         }
     }
 
-    pub fn retriable(&self) -> bool {
+    // the batch stopped, set by `Downloader::set_quota` and `Downloader::set_min_free_space`
+    pub fn stops_batch(&self) -> bool {
+        matches!(self, Self::QuotaExceeded(_) | Self::InsufficientSpace(..))
+    }
+
+    // errors that are worth trying the next mirror for
+    fn mirror_failure(&self) -> bool {
+        self.retriable() || matches!(self, Self::ResourceNotFound | Self::RequestNotOK(_))
+    }
+
+    // errors that count against the health of the proxy used for the request
+    fn is_proxy_failure(&self) -> bool {
         match self {
-            Self::HttpError(httpError) => {
-                if httpError.is_connect() || httpError.is_timeout() {
-                    true
-                } else {
-                    false
-                }
-            }
-            Self::ProxyError(_) => true,
+            Self::HttpError(http_error) => http_error.is_connect(),
+            Self::ProxyError(_) | Self::ConnectTimeout => true,
             _ => false,
         }
     }
@@ -159,7 +422,7 @@ impl std::fmt::Display for Error {
             Error::FolderExistedAsFile => write!(f, "Folder Existed As File"),
             Error::FileIsNotBinary => write!(f, "File Is Not Binary"),
             Error::ResourceNotFound => write!(f, "404 Resource Not Found"),
-            Error::HttpError(http) => write!(f, "HTTP Error: {}", http.to_string()),
+            Error::HttpError(http) => write!(f, "HTTP Error: {}", http),
             Error::UrlIllegal => write!(f, "Url Illegal"),
             Error::UrlCannotDownload => write!(f, "Url Cannot Be Downloaded"),
             Error::RequestNotOK(status_code) => {
@@ -170,178 +433,522 @@ impl std::fmt::Display for Error {
             Error::HashingError => write!(f, "Hashing Error"),
             Error::HashingErrorWhenRename => write!(f, "Hashing Error When Rename"),
             Error::ProxyError(e) => write!(f, "Proxy Error: {}", e),
+            Error::TlsError(e) => write!(f, "TLS Error: {}", e),
+            Error::TooManyRedirects(limit) => write!(f, "More Than {} Redirects", limit),
+            Error::RedirectToOtherHost(url) => write!(f, "Redirect To Other Host: {}", url),
+            Error::ConnectTimeout => write!(f, "Connect Timeout"),
+            Error::ReadIdleTimeout(idle) => {
+                write!(f, "No Data Received In {} Seconds", idle.as_secs_f64())
+            }
+            Error::LowSpeed(speed, period) => write!(
+                f,
+                "Slower Than {} Bytes/s For {} Seconds",
+                speed,
+                period.as_secs_f64()
+            ),
+            Error::ResolveOverrideIllegal(spec) => write!(f, "Resolve Override Illegal: {}", spec),
+            Error::TemplateIllegal(e) => write!(f, "Template Illegal: {}", e),
+            Error::PatternIllegal(e) => write!(f, "Pattern Illegal: {}", e),
+            Error::InputIllegal(e) => write!(f, "Input Illegal: {}", e),
+            Error::ChecksumIllegal(e) => write!(f, "Checksum Illegal: {}", e),
+            Error::ChecksumMismatch(algorithm) => write!(f, "Checksum Mismatch: {}", algorithm),
+            Error::PlaylistIllegal(e) => write!(f, "Playlist Illegal: {}", e),
+            Error::EncryptionUnsupported(method) => {
+                write!(f, "Encryption Unsupported: {}", method)
+            }
+            Error::DecryptionFailed => write!(f, "Decryption Failed"),
+            Error::SizeMismatch(expected, received) => write!(
+                f,
+                "Size Mismatch: expected {} bytes, received {} bytes",
+                expected, received
+            ),
+            Error::FtpError(code, text) => write!(f, "FTP Error {}: {}", code, text),
+            Error::HookFailed(e) => write!(f, "Hook Failed: {}", e),
+            Error::FileMissing => write!(f, "File Missing"),
+            Error::QuotaExceeded(quota) => write!(f, "Quota Of {} Bytes Exceeded", quota),
+            Error::InsufficientSpace(needed, available) => write!(
+                f,
+                "Insufficient Space: {} Bytes Needed, {} Available",
+                needed, available
+            ),
         }
     }
 }
 
 impl From<HttpError> for Error {
     fn from(err: HttpError) -> Self {
-        Self::HttpError(err)
+        if err.is_connect() && err.is_timeout() {
+            Self::ConnectTimeout
+        } else {
+            Self::HttpError(err)
+        }
     }
 }
 
-impl Downloader {
-    async fn dl_worker(
-        client: &reqwest::Client,
-        url: &str,
-        path: &PathBuf,
-        filename: &Option<String>,
-        hash_check: bool,
-        only_binary: bool,
-        auto_rename: bool,
-    ) -> Result<(), Error> {
-        let url = Url::parse(&url).map_err(|_| Error::UrlIllegal)?;
+// the headers of the downloader with those of a task in place of the same names
+fn merge_headers(defaults: &HeaderMap, task: &HeaderMap) -> HeaderMap {
+    let mut headers = defaults.clone();
+    for name in task.keys() {
+        headers.remove(name);
+    }
+    for (name, value) in task {
+        headers.append(name, value.clone());
+    }
+    headers
+}
 
+impl Default for Downloader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Downloader {
+    fn resolve_filename(url: &Url, filename: &Option<String>) -> Result<String, Error> {
         let filename = if let Some(filename) = filename {
             filename.to_string()
         } else {
             match url.path_segments() {
-                Some(l) => l.last().unwrap_or(""),
+                Some(mut l) => l.next_back().unwrap_or(""),
                 None => "",
             }
             .to_string()
         };
-        if filename == "" {
+        if filename.is_empty() {
+            if url.scheme() == "data" {
+                return Ok(scheme::data_filename(url));
+            }
             return Err(Error::UrlCannotDownload);
         }
-        let path = path;
-        let filepath = &path.join(&filename);
-        let path_metadata = fs::metadata(&path);
-        let existed_hash = match path_metadata {
-            Ok(metadata) => {
-                if metadata.is_dir() {
-                    let file_metadata = fs::metadata(filepath);
+        Ok(filename)
+    }
+
+    // where the task saves, a url without a filename is fine when it is not saved to a file
+    fn filepath(
+        path: &Path,
+        url: &Url,
+        filename: &Option<String>,
+        options: &WorkerOptions,
+    ) -> Result<PathBuf, Error> {
+        match Self::resolve_filename(url, filename) {
+            Ok(filename) => Ok(path.join(filename)),
+            Err(_) if !options.sink.is_file() => Ok(path.to_path_buf()),
+            Err(e) => Err(e),
+        }
+    }
+
+    // look at the file already at `filepath`, creating `path` when it is missing
+    fn check_existing(
+        path: &Path,
+        filepath: &Path,
+        options: &WorkerOptions,
+    ) -> Result<Existing, Error> {
+        // entries of the same name are settled by the archive, other sinks have no file
+        if options.archived() || !options.sink.is_file() {
+            return Ok(Existing::None);
+        }
+        let storage = &*options.storage;
+        let path_metadata = storage.metadata(path);
+        let existing = match path_metadata {
+            Some(metadata) => {
+                if metadata.is_dir {
+                    let file_metadata = storage.metadata(filepath);
                     match file_metadata {
-                        Ok(file_metadata) => {
-                            if file_metadata.is_dir() {
+                        Some(file_metadata) => {
+                            if file_metadata.is_dir {
                                 return Err(Error::FileExistedAsFolder);
-                            } else {
-                                if hash_check {
-                                    let mut hasher = Hasher::new();
-                                    std::io::copy(
-                                        &mut fs::File::open(filepath)
-                                            .map_err(|e| Error::IoError(e.to_string()))?,
-                                        &mut hasher,
-                                    )
-                                    .map_err(|_| Error::HashingError)?;
-                                    Some(hasher.finalize())
-                                } else {
-                                    return Err(Error::FileExisted);
+                            }
+                            match options.conflict {
+                                ConflictPolicy::Skip => return Err(Error::FileExisted),
+                                ConflictPolicy::CompareHash => {
+                                    Existing::Hash(conflict::hash_file(storage, filepath)?)
+                                }
+                                ConflictPolicy::KeepNewer => {
+                                    Existing::Modified(conflict::modified(storage, filepath)?)
+                                }
+                                // decided when saving
+                                ConflictPolicy::Overwrite | ConflictPolicy::KeepBoth => {
+                                    Existing::None
                                 }
                             }
                         }
-                        Err(_) => None,
+                        None => Existing::None,
                     }
                 } else {
                     return Err(Error::FolderExistedAsFile);
                 }
             }
-            Err(_) => {
-                fs::create_dir_all(&path).map_err(|e| {
-                    println!("{}: {}", path.to_str().unwrap_or(""), e.to_string());
+            None => {
+                storage.create_dir_all(path).map_err(|e| {
+                    println!("{}: {}", path.to_str().unwrap_or(""), e);
                     Error::FailedToCreateFolder
                 })?;
-                None
+                Existing::None
+            }
+        };
+        Ok(existing)
+    }
+
+    async fn dl_worker(
+        client: &reqwest::Client,
+        url: &str,
+        path: &Path,
+        filename: &Option<String>,
+        options: &WorkerOptions,
+        redirects: &mut Vec<String>,
+    ) -> Result<(), Error> {
+        let url = Url::parse(url).map_err(|_| Error::UrlIllegal)?;
+        if let Some(budget) = &options.budget {
+            budget.proceed()?;
+        }
+
+        if scheme::SCHEMES.contains(&url.scheme()) {
+            let filepath = Self::filepath(path, &url, filename, options)?;
+            let existing = Self::check_existing(path, &filepath, options)?;
+            let (headers, content) =
+                scheme::fetch(&url, options.speed, &options.connection).await?;
+            Self::check(&content, &headers, options)?;
+            return Self::save(
+                &filepath,
+                existing,
+                &content,
+                &headers,
+                url.as_str(),
+                options,
+            )
+            .await;
+        }
+        let cached = options.cache.as_ref().and_then(|cache| cache.lookup(&url));
+        let mut headers = options.headers.clone();
+        if let Some(cached) = &cached {
+            // the task can tell by itself whether the cached content is the one it wants
+            let verifiable = options.checksum.is_some() || options.size.is_some();
+            if verifiable && !options.filename_from_final_url {
+                if let Ok(content) = cached.read() {
+                    if Self::check(&content, &cached.headers, options).is_ok() {
+                        let filepath = Self::filepath(path, &url, filename, options)?;
+                        let existing = Self::check_existing(path, &filepath, options)?;
+                        let headers = &cached.headers;
+                        return Self::save(
+                            &filepath,
+                            existing,
+                            &content,
+                            headers,
+                            url.as_str(),
+                            options,
+                        )
+                        .await;
+                    }
+                }
+            }
+            cached.add_validators(&mut headers);
+        }
+        let (filepath, existing, content) = if options.filename_from_final_url {
+            // the name is only known once redirects are resolved
+            let (final_url, content) =
+                redirect::fetch(client, url.clone(), &headers, options.redirect, redirects).await?;
+            let filepath = Self::filepath(path, &final_url, filename, options)?;
+            let existing = Self::check_existing(path, &filepath, options)?;
+            (filepath, existing, content)
+        } else {
+            let filepath = Self::filepath(path, &url, filename, options)?;
+            let existing = Self::check_existing(path, &filepath, options)?;
+            if let Existing::Modified(modified) = existing {
+                headers.insert(
+                    IF_MODIFIED_SINCE,
+                    HeaderValue::from_str(&httpdate::fmt_http_date(modified)).unwrap(),
+                );
             }
+            let (_, content) =
+                redirect::fetch(client, url.clone(), &headers, options.redirect, redirects).await?;
+            (filepath, existing, content)
         };
-        let req = client.get(url).build()?;
-        let content = client.execute(req).await?;
-        if content.status() != 200 {
+        let filepath = &filepath;
+        let (content_header, content) = if content.status() == StatusCode::NOT_MODIFIED {
+            match &cached {
+                // still what the cache holds
+                Some(cached) => (cached.headers.clone(), cached.read()?),
+                None => return Err(Error::FileExisted),
+            }
+        } else if content.status() != 200 {
             if content.status() == 404 {
-                Err(Error::ResourceNotFound)
+                return Err(Error::ResourceNotFound);
             } else {
-                Err(Error::RequestNotOK(content.status().as_u16()))
+                return Err(Error::RequestNotOK(content.status().as_u16()));
             }
         } else {
+            if let (Some(budget), Some(len)) = (&options.budget, content.content_length()) {
+                if options.sink.is_file() {
+                    let space = (!options.archived()).then_some(filepath.as_path());
+                    budget.admit(&*options.storage, space, len)?;
+                }
+            }
             let content_header = content.headers().clone();
-            let content = content.bytes().await?;
-            // check type
-            if only_binary {
-                if let Some(content_type) = content_header.get("content-type") {
-                    if content_type == "application/javascript"
-                        || content_type.to_str().unwrap().contains("text/html")
-                    {
-                        return Err(Error::FileIsNotBinary);
-                    }
+            (
+                content_header,
+                transfer::read_body(content, options.speed).await?,
+            )
+        };
+        Self::check(&content, &content_header, options)?;
+        let final_url = redirects.last().map_or(url.as_str(), String::as_str);
+        Self::save(
+            filepath,
+            existing,
+            &content,
+            &content_header,
+            final_url,
+            options,
+        )
+        .await?;
+        if let Some(cache) = &options.cache {
+            cache.remember(&url, &content, &content_header)?;
+        }
+        Ok(())
+    }
+
+    // make sure the content is what the task asked for
+    fn check(content: &[u8], headers: &HeaderMap, options: &WorkerOptions) -> Result<(), Error> {
+        // check type
+        if options.only_binary {
+            if let Some(content_type) = headers.get("content-type") {
+                if content_type == "application/javascript"
+                    || content_type.to_str().unwrap().contains("text/html")
+                {
+                    return Err(Error::FileIsNotBinary);
                 }
             }
+        }
+        if let Some(size) = options.size {
+            if content.len() as u64 != size {
+                return Err(Error::SizeMismatch(size, content.len() as u64));
+            }
+        }
+        if let Some(checksum) = &options.checksum {
+            if !checksum.verify(content) {
+                return Err(Error::ChecksumMismatch(checksum.algorithm));
+            }
+        }
+        Ok(())
+    }
+
+    // write `content` fetched from `url` to `filepath`, settling conflicts with the file
+    // found there before
+    async fn save(
+        filepath: &PathBuf,
+        existing: Existing,
+        content: &[u8],
+        headers: &HeaderMap,
+        url: &str,
+        options: &WorkerOptions,
+    ) -> Result<(), Error> {
+        // removes the name `KeepBoth` reserved when the content does not make it there
+        let mut reservation = None;
+        let target = match existing {
             // check hash
-            if let Some(existed_hash) = existed_hash {
-                let mut hasher = Hasher::new();
-                std::io::copy(&mut content.as_ref(), &mut hasher)
-                    .map_err(|_| Error::HashingError)?;
-                if hasher.finalize() == existed_hash {
+            Existing::Hash(existed_hash) => {
+                return if blake3::hash(content) == existed_hash {
+                    if let Some(manifest) = &options.manifest {
+                        manifest.record(filepath, content);
+                    }
                     Ok(())
                 } else {
                     Err(Error::DifferentFileExisted)
+                };
+            }
+            Existing::Modified(modified) => {
+                if !conflict::is_newer(headers, modified) {
+                    return Err(Error::FileExisted);
                 }
-            } else {
-                std::io::copy(
-                    &mut content.as_ref(),
-                    &mut fs::File::create(filepath).map_err(|e| Error::IoError(e.to_string()))?,
-                )
-                .map_err(|e| Error::IoError(e.to_string()))?;
-                if auto_rename {
-                    // rename file without extension via using mime types
-                    if !filepath
+                filepath.clone()
+            }
+            Existing::None if !options.sink.is_file() => {
+                if let Some(cache) = &options.cache {
+                    cache.insert(content)?;
+                }
+                *options.delivered.lock().unwrap() = Some(content.to_vec());
+                return Ok(());
+            }
+            Existing::None => {
+                let mut target = filepath.clone();
+                // rename file without extension via using mime types
+                if options.auto_rename
+                    && !filepath
                         .file_name()
                         .unwrap()
                         .to_string_lossy()
                         .contains('.')
-                    {
-                        let ext = content_header
-                            .get("content-type")
-                            .map_or("", |h| h.to_str().unwrap_or(""));
-                        let ext = ext.split('/').last().unwrap().split(';').next().unwrap();
-                        let mut new_file_path = filepath.clone();
-                        new_file_path.set_extension(ext);
-                        if new_file_path.exists() {
-                            if new_file_path.is_dir() {
-                                // give up
-                                return Err(Error::FileExistedAsFolderWhenRename);
-                            }
-                            // using hash to check
-                            let mut hasher = Hasher::new();
-                            std::io::copy(&mut content.as_ref(), &mut hasher)
-                                .map_err(|_| Error::HashingErrorWhenRename)?;
-                            let old_hash = hasher.finalize();
-                            hasher.reset();
-                            std::io::copy(
-                                &mut fs::File::open(new_file_path)
-                                    .map_err(|e| Error::IoErrorWhenRename(e.to_string()))?,
-                                &mut hasher,
-                            )
-                            .map_err(|_| Error::HashingErrorWhenRename)?;
-                            let new_hash = hasher.finalize();
-                            if old_hash != new_hash {
-                                return Err(Error::DifferentFileExistedWhenRename);
-                            }
-                        } else {
-                            fs::copy(filepath, new_file_path)
-                                .map_err(|e| Error::IoErrorWhenRename(e.to_string()))?;
+                {
+                    let ext = headers
+                        .get("content-type")
+                        .map_or("", |h| h.to_str().unwrap_or(""));
+                    let ext = ext
+                        .split('/')
+                        .next_back()
+                        .unwrap()
+                        .split(';')
+                        .next()
+                        .unwrap();
+                    target.set_extension(ext);
+                }
+                #[cfg(feature = "archive")]
+                if let Some(archive) = &options.archive {
+                    if let Some(budget) = &options.budget {
+                        budget.consume(&*options.storage, None, content.len() as u64)?;
+                    }
+                    if let Some(cache) = &options.cache {
+                        cache.insert(content)?;
+                    }
+                    let name = archive.add(&target, content, headers, options.conflict)?;
+                    if let Some(manifest) = &options.manifest {
+                        manifest.record_entry(name, content);
+                    }
+                    return Ok(());
+                }
+                let when_rename = &target != filepath;
+                match conflict::resolve(
+                    &*options.storage,
+                    &target,
+                    options.conflict,
+                    content,
+                    headers,
+                    when_rename,
+                )? {
+                    Some(resolved) => {
+                        reservation = Some(conflict::Reservation::new(
+                            &*options.storage,
+                            &target,
+                            &resolved,
+                        ));
+                        resolved
+                    }
+                    // the same content is already there
+                    None => {
+                        if let Some(manifest) = &options.manifest {
+                            manifest.record(&target, content);
                         }
-                        fs::remove_file(filepath)
-                            .map_err(|e| Error::IoErrorWhenRename(e.to_string()))?;
+                        return Ok(());
                     }
                 }
-                Ok(())
             }
+        };
+        if let Some(budget) = &options.budget {
+            budget.consume(&*options.storage, Some(&target), content.len() as u64)?;
+        }
+        Self::write(options, &target, content)?;
+        if let Some(reservation) = reservation {
+            reservation.keep();
+        }
+        if options.mtime || options.origin.is_some() {
+            origin::record(
+                &*options.storage,
+                &target,
+                url,
+                headers,
+                options.mtime,
+                options.origin,
+            )?;
+        }
+        if !options.hooks.is_empty() {
+            // hooks block on io, they run away from the download tasks
+            let hooks = Arc::clone(&options.hooks);
+            let storage = Arc::clone(&options.storage);
+            let manifest = options.manifest.clone();
+            let (path, url, headers) = (target.clone(), url.to_string(), headers.clone());
+            let hash = blake3::hash(content);
+            tokio::task::spawn_blocking(move || {
+                let mut file = FinishedFile {
+                    path,
+                    url: &url,
+                    headers: &headers,
+                    hash,
+                    storage: &*storage,
+                };
+                for hook in hooks.iter() {
+                    hook.finished(&mut file)?;
+                }
+                // where the hooks left it, with what they made of it
+                match manifest {
+                    Some(manifest) => manifest.record_stored(&*storage, &file.path),
+                    None => Ok(()),
+                }
+            })
+            .await
+            .map_err(|e| Error::HookFailed(e.to_string()))??;
+        } else if let Some(manifest) = &options.manifest {
+            manifest.record(&target, content);
+        }
+        Ok(())
+    }
+
+    // write under a temporary name and move it over `target` once complete,
+    // linked from the cache when there is one and no metadata goes on the file
+    fn write(options: &WorkerOptions, target: &Path, content: &[u8]) -> Result<(), Error> {
+        let storage = &*options.storage;
+        let mut partial = target.as_os_str().to_owned();
+        partial.push(".part");
+        let partial = PathBuf::from(partial);
+        // an mtime or xattrs set on a linked file would change the cached object for all
+        let linked = !options.mtime && options.origin != Some(OriginMetadata::Xattr);
+        let object = match &options.cache {
+            Some(cache) => Some(cache.insert(content)?),
+            None => None,
+        };
+        let written = match object {
+            Some(object) if linked => storage.link(&object, &partial),
+            _ => storage.create(&partial).and_then(|mut file| {
+                file.write_all(content)?;
+                file.flush()
+            }),
+        };
+        if let Err(e) = written.and_then(|_| storage.rename(&partial, target)) {
+            let _ = storage.remove(&partial);
+            return Err(Error::IoError(e.to_string()));
         }
+        Ok(())
     }
 
     pub fn new() -> Self {
         Self {
             list: vec![],
+            queued: Dedupe::new(None),
+            receiver: None,
+            stream_window: 65536,
             folder: Default::default(),
+            template: None,
+            mirror: false,
             timeout: Some(Duration::from_secs(10)),
+            connect_timeout: None,
+            speed: SpeedLimits::default(),
             headers: HeaderMap::new(),
-            hash_check: false,
+            conflict: ConflictPolicy::Skip,
             only_binary: true,
             auto_rename: true,
+            redirect: RedirectPolicy::default(),
+            filename_from_final_url: false,
             proxies: Vec::new(),
+            proxy_strategy: ProxyStrategy::Combined,
+            proxy_failure_threshold: 3,
+            proxy_cooldown: Duration::from_secs(60),
+            root_certificates: Vec::new(),
+            identity: None,
+            min_tls_version: None,
+            accept_invalid_certs: false,
+            dns_overrides: HashMap::new(),
+            ip_family: IpFamily::Any,
+            local_address: None,
             disable_default_proxy: false,
             task_count: 8,
             retries: 0,
+            storage: Arc::new(LocalStorage),
+            #[cfg(feature = "archive")]
+            archive: None,
+            cache: None,
+            mtime: false,
+            origin: None,
+            hooks: vec![],
+            manifests: vec![],
+            quota: None,
+            min_free_space: None,
         }
     }
 
@@ -349,12 +956,52 @@ impl Downloader {
         self.folder = download_folder;
     }
 
+    // lay out tasks without an explicit filename by the template, relative to their path
+    pub fn set_path_template(&mut self, template: PathTemplate) {
+        self.template = Some(template);
+    }
+
+    // save tasks without filename or template as `<host>/<url path>` like `wget -x`
+    pub fn set_mirror_mode(&mut self, mirror: bool) {
+        self.mirror = mirror;
+    }
+
+    // total time allowed for a request, including reading the body, ftp applies it to
+    // every read and write
     pub fn set_timeout(&mut self, timeout: Duration) {
         self.timeout = Some(timeout);
     }
 
+    // no total timeout, rely on the connect, idle and speed limits instead
+    pub fn disable_timeout(&mut self) {
+        self.timeout = None;
+    }
+
+    pub fn set_connect_timeout(&mut self, timeout: Duration) {
+        self.connect_timeout = Some(timeout);
+    }
+
+    // abort when no data arrives for `timeout`
+    pub fn set_read_idle_timeout(&mut self, timeout: Duration) {
+        self.speed.idle = Some(timeout);
+    }
+
+    // abort when slower than `bytes_per_sec` for `period`
+    pub fn set_low_speed_limit(&mut self, bytes_per_sec: u64, period: Duration) {
+        self.speed.low_speed = Some((bytes_per_sec, period));
+    }
+
+    // same as `ConflictPolicy::CompareHash` when enabled and `ConflictPolicy::Skip` when not
     pub fn set_hash_check(&mut self, hash_check: bool) {
-        self.hash_check = hash_check;
+        self.conflict = if hash_check {
+            ConflictPolicy::CompareHash
+        } else {
+            ConflictPolicy::Skip
+        };
+    }
+
+    pub fn set_conflict_policy(&mut self, conflict: ConflictPolicy) {
+        self.conflict = conflict;
     }
 
     pub fn set_binary_only(&mut self, only_binary: bool) {
@@ -365,10 +1012,39 @@ impl Downloader {
         self.auto_rename = auto_rename;
     }
 
+    pub fn set_redirect_limit(&mut self, limit: usize) {
+        self.redirect.limit = limit;
+    }
+
+    // fail with `RedirectToOtherHost` instead of following a redirect off the original host
+    pub fn set_redirect_same_host_only(&mut self, same_host_only: bool) {
+        self.redirect.same_host_only = same_host_only;
+    }
+
+    // name files after the url at the end of the redirect chain instead of the requested one
+    pub fn set_filename_from_final_url(&mut self, from_final_url: bool) {
+        self.filename_from_final_url = from_final_url;
+    }
+
     pub fn set_retries_count(&mut self, retries: usize) {
         self.retries = retries;
     }
 
+    // stop the batch before it writes more than `quota` bytes to the destination, counting
+    // the files it saves. The task that would go over and the ones not started yet are
+    // reported with `Error::QuotaExceeded`
+    pub fn set_quota(&mut self, quota: Option<u64>) {
+        self.quota = quota;
+    }
+
+    // stop the batch before a file would leave less than `min_free_space` bytes free on the
+    // destination, checked with `Content-Length` before the body is downloaded when the
+    // server sends it. Like `set_quota`, the tasks left are reported with
+    // `Error::InsufficientSpace`
+    pub fn set_min_free_space(&mut self, min_free_space: Option<u64>) {
+        self.min_free_space = min_free_space;
+    }
+
     pub fn add_proxy(&mut self, proxy_type: ProxyType, proxy: String) -> Result<(), Error> {
         let proxy = match proxy_type {
             ProxyType::Http => Proxy::http(proxy),
@@ -388,28 +1064,183 @@ impl Downloader {
         self.disable_default_proxy = false;
     }
 
+    pub fn set_proxy_strategy(&mut self, strategy: ProxyStrategy) {
+        self.proxy_strategy = strategy;
+    }
+
+    // a proxy failing `threshold` times in a row is skipped for `cooldown`
+    pub fn set_proxy_health(&mut self, threshold: usize, cooldown: Duration) {
+        self.proxy_failure_threshold = threshold;
+        self.proxy_cooldown = cooldown;
+    }
+
+    // trust every certificate in a PEM bundle in addition to the system roots
+    pub fn add_root_certificate(&mut self, pem_file: PathBuf) -> Result<(), Error> {
+        let pem = fs::read(&pem_file).map_err(|e| Error::TlsError(e.to_string()))?;
+        let certs =
+            Certificate::from_pem_bundle(&pem).map_err(|e| Error::TlsError(e.to_string()))?;
+        if certs.is_empty() {
+            return Err(Error::TlsError(format!(
+                "no certificate found in {}",
+                pem_file.display()
+            )));
+        }
+        self.root_certificates.extend(certs);
+        Ok(())
+    }
+
+    // client certificate for mTLS, both files are PEM and the key is PKCS#8
+    pub fn set_client_certificate(
+        &mut self,
+        cert_file: PathBuf,
+        key_file: PathBuf,
+    ) -> Result<(), Error> {
+        let cert = fs::read(cert_file).map_err(|e| Error::TlsError(e.to_string()))?;
+        let key = fs::read(key_file).map_err(|e| Error::TlsError(e.to_string()))?;
+        let identity =
+            Identity::from_pkcs8_pem(&cert, &key).map_err(|e| Error::TlsError(e.to_string()))?;
+        self.identity = Some(identity);
+        Ok(())
+    }
+
+    // native-tls cannot require TLS 1.3, building the client fails with `TLS_1_3`
+    pub fn set_min_tls_version(&mut self, version: TlsVersion) {
+        self.min_tls_version = Some(version);
+    }
+
+    // accept invalid or self-signed certificates, only for testing
+    pub fn danger_accept_invalid_certs(&mut self, accept: bool) {
+        self.accept_invalid_certs = accept;
+    }
+
+    // curl-style `host:port:addr[,addr]`, the port is ignored and taken from the url
+    pub fn add_resolve_override(&mut self, spec: &str) -> Result<(), Error> {
+        let (host, addrs) = dns::parse_resolve(spec)?;
+        self.dns_overrides.entry(host).or_default().extend(addrs);
+        Ok(())
+    }
+
+    pub fn set_ip_family(&mut self, family: IpFamily) {
+        self.ip_family = family;
+    }
+
+    // bind outgoing connections to the address of one interface
+    pub fn set_local_address(&mut self, address: IpAddr) {
+        self.local_address = Some(address);
+    }
+
     pub fn set_task_count(&mut self, task_count: usize) {
         self.task_count = task_count;
     }
 
+    // write finished files through `storage` instead of the local filesystem
+    pub fn set_storage<S: Storage + 'static>(&mut self, storage: S) {
+        self.storage = Arc::new(storage);
+    }
+
+    // write every finished file into one archive at `archive` instead of the destination,
+    // entries are named after the path relative to the destination
+    #[cfg(feature = "archive")]
+    pub fn set_archive(&mut self, archive: PathBuf, format: ArchiveFormat) {
+        self.archive = Some((archive, format));
+    }
+
+    // keep every downloaded content once in `cache_dir`, keyed by blake3, and hard link it
+    // into place. A url downloaded before is served from the cache when the checksum or size
+    // of the task match it, or when the server answers its validators with 304. Files are
+    // copied instead of linked when the mtime or extended attributes are set on them.
+    pub fn set_cache(&mut self, cache_dir: PathBuf) {
+        self.cache = Some(cache_dir);
+    }
+
+    // set the modification time of saved files from `Last-Modified`
+    pub fn set_mtime_from_server(&mut self, mtime: bool) {
+        self.mtime = mtime;
+    }
+
+    // record the url, ETag and content type of saved files
+    pub fn set_origin_metadata(&mut self, origin: Option<OriginMetadata>) {
+        self.origin = origin;
+    }
+
+    // run `hook` on every file saved to the destination, after the hooks added before it
+    pub fn add_hook<H: Hook + 'static>(&mut self, hook: H) {
+        self.hooks.push(Box::new(hook));
+    }
+
+    // write a manifest of the files saved to the destination at `path` once the batch
+    // completes. Files are listed as the hooks left them, a folder extracted by one file by
+    // file, and in archive mode the entries of the archive. Files outside the destination
+    // are left out.
+    pub fn add_manifest(&mut self, path: PathBuf, format: ManifestFormat) {
+        self.manifests.push((path, format));
+    }
+
     // path is relative to Downloader global folder
     pub fn append_task<T: Into<DownloadTask>>(&mut self, task: T) {
         let task = task.into();
-        let url = task.url;
-        let path = task.path;
-        let filename = task.filename;
-        if self
-            .list
-            .iter()
-            .any(|x| x.url == url && x.path == path && x.filename == filename)
-        {
-            return;
+        if self.queued.insert(&task) {
+            self.list.push(task);
         }
-        self.list.push(DownloadParams {
-            url,
-            path,
-            filename,
-        });
+    }
+
+    // fetch an HTML page, sitemap or feed with the client of the downloader and append a task
+    // for every link accepted by `filter`, returns the number of links found
+    #[cfg(feature = "links")]
+    pub fn append_links(&mut self, page: &str, filter: &LinkFilter) -> Result<usize, Error> {
+        let page = Url::parse(page).map_err(|_| Error::UrlIllegal)?;
+        let pool = ProxyPool::new(
+            self.proxy_strategy,
+            self.proxies.clone(),
+            self.proxy_failure_threshold,
+            self.proxy_cooldown,
+            |proxies| self.build_client(proxies),
+        )?;
+        let (_, client) = pool.pick(page.host_str().unwrap_or_default(), None);
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let links = rt.block_on(links::crawl(
+            client,
+            page,
+            &self.headers,
+            self.redirect,
+            self.speed,
+            filter,
+        ))?;
+        let count = links.len();
+        for link in links {
+            self.append_task((link.to_string(), PathBuf::from("."), None));
+        }
+        Ok(count)
+    }
+
+    // tasks sent through the channel are downloaded along with the list, and the download
+    // only returns after every sender is dropped. `buffer` tasks can wait in the channel.
+    // Calling it again closes the previous channel.
+    pub fn task_channel(&mut self, buffer: usize) -> TaskSender {
+        let (sender, receiver) = mpsc::channel(buffer.max(1));
+        self.receiver = Some(receiver);
+        TaskSender(sender)
+    }
+
+    // only the last `window` tasks sent through the channel are remembered to drop
+    // duplicates, so a long stream takes bounded memory. 0 sends every task through.
+    pub fn set_stream_dedupe_window(&mut self, window: usize) {
+        self.stream_window = window;
+    }
+
+    // expand the url of the task as a `UrlPattern` and append a task for every url, returns
+    // how many were appended as urls already queued are left out
+    pub fn append_pattern<T: Into<DownloadTask>>(&mut self, task: T) -> Result<usize, Error> {
+        let task = task.into();
+        let pattern = UrlPattern::parse(&task.url)?;
+        let queued = self.list.len();
+        for url in pattern.iter() {
+            self.append_task(DownloadTask {
+                url,
+                ..task.clone()
+            });
+        }
+        Ok(self.list.len() - queued)
     }
 
     pub fn add_header<K, V>(&mut self, key: K, value: V)
@@ -420,18 +1251,20 @@ impl Downloader {
         self.headers.append(key, value.into());
     }
 
-    pub fn download<F: 'static>(self, callback: F) -> Result<Vec<DownloadFailed>, Error>
-    where
-        F: Fn(&str, &PathBuf, &Option<String>, Option<&Error>) + std::marker::Send,
-    {
-        let client = Client::builder().default_headers(self.headers);
+    fn build_client(&self, proxies: Vec<Proxy>) -> Result<Client, Error> {
+        // headers are sent per request, so `redirect::fetch` can drop credentials
+        let client = Client::builder().redirect(Policy::none()); // followed by `redirect::fetch`
         let client = if let Some(timeout) = self.timeout {
             client.timeout(timeout)
         } else {
             client
         };
-        let client = self
-            .proxies
+        let client = if let Some(connect_timeout) = self.connect_timeout {
+            client.connect_timeout(connect_timeout)
+        } else {
+            client
+        };
+        let client = proxies
             .into_iter()
             .fold(client, |client, proxy| client.proxy(proxy));
         let client = if self.disable_default_proxy {
@@ -439,72 +1272,378 @@ impl Downloader {
         } else {
             client
         };
-        let client = client.build().map_err(|e| Error::HttpError(e))?;
-        let hash_check = self.hash_check;
-        let only_binary = self.only_binary;
-        let auto_rename = self.auto_rename;
+        let client = self.root_certificates.iter().fold(client, |client, cert| {
+            client.add_root_certificate(cert.clone())
+        });
+        let client = if let Some(identity) = &self.identity {
+            client.identity(identity.clone())
+        } else {
+            client
+        };
+        let client = if let Some(version) = self.min_tls_version {
+            client.min_tls_version(version)
+        } else {
+            client
+        };
+        let client = client.danger_accept_invalid_certs(self.accept_invalid_certs);
+        let client = if !self.dns_overrides.is_empty() || self.ip_family != IpFamily::Any {
+            client.dns_resolver(Arc::new(dns::Resolver {
+                overrides: self.dns_overrides.clone(),
+                family: self.ip_family,
+            }))
+        } else {
+            client
+        };
+        let client = client.local_address(self.local_address);
+        client.build().map_err(Error::HttpError)
+    }
+
+    // folder and filename of a task below `workdir` after templates and mirror layout,
+    // with the error of a template that cannot be rendered for it
+    fn locate(
+        t: &DownloadTask,
+        index: usize,
+        template: Option<&PathTemplate>,
+        mirror: bool,
+        workdir: &Path,
+    ) -> (PathBuf, Option<String>, Result<(), Error>) {
+        let path = workdir.join(&t.path);
+        let layout = match (t.template.as_ref().or(template), &t.filename) {
+            (_, Some(_)) => None,
+            (Some(template), None) => Some(template.render(&t.url, index)),
+            (None, None) if mirror => Some(template::mirror_layout(&t.url)),
+            (None, None) => None,
+        };
+        let (path, filename, rendered) = match layout {
+            Some(Ok((dir, filename))) => (path.join(dir), Some(filename), Ok(())),
+            Some(Err(e)) => (path, None, Err(e)),
+            None => (path, t.filename.clone(), Ok(())),
+        };
+        // mirrors are saved under the name of the first url
+        let filename = match filename {
+            None if !t.mirrors.is_empty() => Url::parse(&t.url)
+                .ok()
+                .and_then(|url| Self::resolve_filename(&url, &None).ok()),
+            filename => filename,
+        };
+        (path, filename, rendered)
+    }
+
+    // check the files the tasks would be saved to without fetching anything. Every task gets
+    // a report, and the files below the destination that no task accounts for are listed,
+    // leaving out the manifests and the cache folder. A name without extension also matches
+    // the one file auto rename could have given it. Names from the final url are not found,
+    // and archive mode is not covered as nothing is written to the destination.
+    pub fn verify(mut self) -> Result<Verification, Error> {
+        let template = self.template.as_ref();
+        let mut expected: HashSet<PathBuf> = self
+            .manifests
+            .iter()
+            .map(|(path, _)| path.clone())
+            .collect();
+        let reports = std::mem::take(&mut self.list)
+            .into_iter()
+            .enumerate()
+            .map(|(index, t)| {
+                let (path, filename, rendered) =
+                    Self::locate(&t, index, template, self.mirror, &self.folder);
+                let result = rendered.and_then(|()| {
+                    let url = Url::parse(&t.url).map_err(|_| Error::UrlIllegal)?;
+                    #[cfg(feature = "hls")]
+                    let name = match t.hls {
+                        Some(_) => hls::output_name(&url, &filename)?,
+                        None => Self::resolve_filename(&url, &filename)?,
+                    };
+                    #[cfg(not(feature = "hls"))]
+                    let name = Self::resolve_filename(&url, &filename)?;
+                    let mut filepath = path.join(name);
+                    if self.auto_rename && !self.storage.exists(&filepath) {
+                        if let Some(renamed) = verify::renamed(&*self.storage, &filepath) {
+                            filepath = renamed;
+                        }
+                    }
+                    let checked = verify::check(&*self.storage, &filepath, &t.checksum, t.size);
+                    expected.insert(filepath);
+                    checked
+                });
+                VerifyReport {
+                    url: t.url,
+                    path,
+                    filename,
+                    result,
+                }
+            })
+            .collect();
+        let extraneous = verify::files(&*self.storage, &self.folder)
+            .map_err(|e| Error::IoError(e.to_string()))?
+            .into_iter()
+            .filter(|file| !verify::accounted(file, &expected))
+            .filter(|file| {
+                !self
+                    .cache
+                    .as_ref()
+                    .is_some_and(|cache| file.starts_with(cache))
+            })
+            .collect();
+        Ok(Verification {
+            reports,
+            extraneous,
+        })
+    }
+
+    pub fn download<F>(self, callback: F) -> Result<Vec<DownloadFailed>, Error>
+    where
+        F: Fn(&str, &PathBuf, &Option<String>, Option<&Error>) + std::marker::Send + 'static,
+    {
+        Ok(self
+            .download_report(callback)?
+            .into_iter()
+            .filter_map(|r| match r.result {
+                Ok(()) => None,
+                Err(err) => Some(DownloadFailed {
+                    url: r.url,
+                    path: r.path,
+                    filename: r.filename,
+                    redirects: r.redirects,
+                    err,
+                }),
+            })
+            .collect())
+    }
+
+    // like `download`, but reports every task including the successful ones
+    pub fn download_report<F>(mut self, callback: F) -> Result<Vec<DownloadReport>, Error>
+    where
+        F: Fn(&str, &PathBuf, &Option<String>, Option<&Error>) + std::marker::Send + 'static,
+    {
+        let pool = Arc::new(ProxyPool::new(
+            self.proxy_strategy,
+            std::mem::take(&mut self.proxies),
+            self.proxy_failure_threshold,
+            self.proxy_cooldown,
+            |proxies| self.build_client(proxies),
+        )?);
+        let options = WorkerOptions {
+            conflict: self.conflict,
+            only_binary: self.only_binary,
+            auto_rename: self.auto_rename,
+            redirect: self.redirect,
+            filename_from_final_url: self.filename_from_final_url,
+            speed: self.speed,
+            connection: Connection {
+                resolver: Arc::new(dns::Resolver {
+                    overrides: self.dns_overrides.clone(),
+                    family: self.ip_family,
+                }),
+                local_address: self.local_address,
+                connect_timeout: self.connect_timeout,
+                timeout: self.timeout,
+            },
+            headers: HeaderMap::new(),
+            checksum: None,
+            size: None,
+            storage: Arc::clone(&self.storage),
+            #[cfg(feature = "archive")]
+            archive: match &self.archive {
+                Some((archive, format)) => Some(Arc::new(ArchiveWriter::create(
+                    archive,
+                    *format,
+                    self.folder.clone(),
+                )?)),
+                None => None,
+            },
+            cache: None,
+            mtime: self.mtime,
+            origin: self.origin,
+            sink: TaskSink::File,
+            delivered: Arc::new(Mutex::new(None)),
+            hooks: Arc::new(std::mem::take(&mut self.hooks)),
+            manifest: if self.manifests.is_empty() {
+                None
+            } else {
+                Some(Arc::new(Manifest::new(
+                    self.folder.clone(),
+                    std::mem::take(&mut self.manifests),
+                )))
+            },
+            budget: if self.quota.is_none() && self.min_free_space.is_none() {
+                None
+            } else {
+                Some(Arc::new(Budget::new(self.quota, self.min_free_space)))
+            },
+        };
+        let retries = self.retries;
         let workdir = self.folder;
-        if !workdir.exists() {
-            fs::create_dir_all(&workdir).map_err(|_| Error::FailedToCreateFolder)?;
-        } else if workdir.is_file() {
-            return Err(Error::FolderExistedAsFile);
+        if !options.archived() {
+            match self.storage.metadata(&workdir) {
+                None => self
+                    .storage
+                    .create_dir_all(&workdir)
+                    .map_err(|_| Error::FailedToCreateFolder)?,
+                Some(metadata) if !metadata.is_dir => return Err(Error::FolderExistedAsFile),
+                Some(_) => {}
+            }
         }
+        let options = match &self.cache {
+            Some(cache_dir) => WorkerOptions {
+                cache: Some(Arc::new(Cache::open(cache_dir.clone())?)),
+                ..options
+            },
+            None => options,
+        };
+        #[cfg(feature = "archive")]
+        let archive = options.archive.clone();
+        let manifest = options.manifest.clone();
         let limits = Arc::new(Semaphore::new(self.task_count)); // limit the tasks
         let callback = Arc::new(Mutex::new(callback));
 
         let rt = tokio::runtime::Runtime::new().unwrap();
-        let jobs: Vec<_> = self
-            .list
-            .into_iter()
-            .map(|t| {
-                let permit = Arc::clone(&limits).acquire_owned();
-                let client = client.clone();
-                let hash_check = hash_check.clone();
-                let only_binary = only_binary.clone();
-                let auto_rename = auto_rename.clone();
-                let workdir = workdir.clone();
-                let path = workdir.join(&t.path);
-                let callback = Arc::clone(&callback);
-                rt.spawn(async move {
-                    let permit = permit.await.unwrap(); // for limiting tasks
-                    let result = Self::dl_worker(
-                        &client,
-                        &t.url,
-                        &path,
-                        &t.filename,
-                        hash_check,
-                        only_binary,
-                        auto_rename,
-                    )
-                    .await;
-                    let callback = &*callback.lock().unwrap();
-                    let r = if let Err(e) = result {
-                        callback(&t.url, &path, &t.filename, Some(&e));
-                        Some(DownloadFailed {
-                            url: t.url,
-                            path,
-                            filename: t.filename,
-                            err: e,
-                        })
-                    } else {
-                        callback(&t.url, &path, &t.filename, None);
-                        None
-                    };
-                    drop(permit);
-                    r
-                })
+        let template = self.template;
+        let mirror = self.mirror;
+        let headers = std::mem::take(&mut self.headers);
+        let spawn = |index: usize, t: DownloadTask| {
+            let permit = Arc::clone(&limits).acquire_owned();
+            #[cfg(feature = "hls")]
+            let limits = Arc::clone(&limits);
+            let pool = Arc::clone(&pool);
+            let mut options = options.clone();
+            options.conflict = t.conflict.unwrap_or(options.conflict);
+            options.headers = merge_headers(&headers, &t.headers);
+            options.checksum = t.checksum.clone();
+            options.size = t.size;
+            options.sink = t.sink.clone();
+            options.delivered = Arc::new(Mutex::new(None));
+            let (path, filename, rendered) =
+                Self::locate(&t, index, template.as_ref(), mirror, &workdir);
+            let urls: Vec<String> = std::iter::once(t.url.clone())
+                .chain(t.mirrors.iter().cloned())
+                .collect();
+            let callback = Arc::clone(&callback);
+            rt.spawn(async move {
+                #[cfg_attr(not(feature = "hls"), allow(unused_mut))]
+                let mut permit = Some(permit.await.unwrap()); // for limiting tasks
+
+                // tasks left when the batch stopped are not started, they report why
+                let stopped = options.budget.as_ref().and_then(|budget| budget.stopped());
+                let mut mirror = 0;
+                let mut attempt = 0;
+                let mut last_proxy = None;
+                let mut redirects = vec![];
+                #[cfg(feature = "hls")]
+                let hls = t.hls;
+                #[cfg(not(feature = "hls"))]
+                let hls: Option<()> = None;
+                let result = match (stopped, rendered, hls) {
+                    (Some(e), _, _) | (None, Err(e), _) => Err(e),
+                    #[cfg(feature = "hls")]
+                    (None, Ok(()), Some(variant)) => {
+                        // segments take slots of their own
+                        permit = None;
+                        hls::download(
+                            Arc::clone(&pool),
+                            limits,
+                            &t.url,
+                            &path,
+                            &filename,
+                            variant,
+                            &options,
+                            retries,
+                        )
+                        .await
+                    }
+                    (None, Ok(()), _) => loop {
+                        redirects.clear();
+                        let url = &urls[mirror % urls.len()];
+                        let host = Url::parse(url)
+                            .ok()
+                            .and_then(|u| u.host_str().map(|h| h.to_string()))
+                            .unwrap_or_default();
+                        // switch to another proxy when retrying
+                        let (proxy, client) = pool.pick(&host, last_proxy);
+                        let result = Self::dl_worker(
+                            client,
+                            url,
+                            &path,
+                            &filename,
+                            &options,
+                            &mut redirects,
+                        )
+                        .await;
+                        pool.report(proxy, &result);
+                        match result {
+                            Err(e) if e.retriable() && attempt < retries => {
+                                attempt += 1;
+                                mirror += 1;
+                                last_proxy = proxy;
+                            }
+                            // another mirror may still have it
+                            Err(e) if e.mirror_failure() && mirror + 1 < urls.len() => {
+                                mirror += 1;
+                            }
+                            result => break result,
+                        }
+                    },
+                };
+                let mut content = None;
+                let result = match result {
+                    Ok(()) if !t.sink.is_file() => {
+                        let delivered = options.delivered.lock().unwrap().take();
+                        match t.sink.deliver(delivered.unwrap_or_default()).await {
+                            Ok(delivered) => {
+                                content = delivered;
+                                Ok(())
+                            }
+                            Err(e) => Err(Error::IoError(e.to_string())),
+                        }
+                    }
+                    result => result,
+                };
+                let callback = &*callback.lock().unwrap();
+                callback(&t.url, &path, &filename, result.as_ref().err());
+                drop(permit);
+                DownloadReport {
+                    url: t.url,
+                    path,
+                    filename,
+                    redirects,
+                    result,
+                    content,
+                }
             })
+        };
+        let mut jobs: Vec<_> = std::mem::take(&mut self.list)
+            .into_iter()
+            .enumerate()
+            .map(|(index, t)| spawn(index, t))
             .collect();
         let mut result = vec![];
         let downloader = async {
-            for job in jobs {
-                let res = job.await.unwrap();
-                if let Some(res) = res {
-                    result.push(res)
+            if let Some(mut receiver) = self.receiver.take() {
+                let mut streamed = Dedupe::new(Some(self.stream_window));
+                loop {
+                    // leave tasks in the channel while every slot is busy, so senders wait. Once
+                    // the batch stopped the rest is still taken, to be reported as not started
+                    drop(limits.acquire().await);
+                    let Some(t) = receiver.recv().await else {
+                        break;
+                    };
+                    if !self.queued.contains(&t) && streamed.insert(&t) {
+                        jobs.push(spawn(jobs.len(), t));
+                    }
                 }
             }
+            for job in jobs {
+                result.push(job.await.unwrap());
+            }
         };
         rt.block_on(downloader);
+        #[cfg(feature = "archive")]
+        if let Some(archive) = archive {
+            archive.finish()?;
+        }
+        if let Some(manifest) = manifest {
+            manifest.finish(&*self.storage)?;
+        }
         Ok(result)
     }
 }
@@ -0,0 +1,281 @@
+#[cfg(test)]
+mod tests {
+    use super::{crawl, extract, LinkFilter};
+    use crate::redirect::RedirectPolicy;
+    use crate::transfer::SpeedLimits;
+    use reqwest::header::HeaderMap;
+    use reqwest::{Client, Url};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn html() {
+        let base = Url::parse("https://example.com/gallery/index.html").unwrap();
+        let page = r#"<html><head><base href="/static/"><!-- <a href="hidden.jpg"> -->
+            <link rel=stylesheet href='site.css'></head>
+            <body><a href="../a.jpg?x=1&amp;y=2#top">a</a>
+            <img SRC=b.png srcset="b@2x.png 2x, https://cdn.example.com/b@3x.png 3x">
+            <a href="mailto:me@example.com">mail</a><a href="javascript:void(0)">js</a>
+            </body></html>"#;
+        let links: Vec<String> = extract(page, &base).iter().map(Url::to_string).collect();
+        assert_eq!(
+            links,
+            vec![
+                "https://example.com/static/site.css",
+                "https://example.com/a.jpg?x=1&y=2",
+                "https://example.com/static/b.png",
+                "https://example.com/static/b@2x.png",
+                "https://cdn.example.com/b@3x.png",
+            ]
+        );
+    }
+
+    #[test]
+    fn feeds() {
+        let base = Url::parse("https://example.com/sitemap.xml").unwrap();
+        let sitemap = "<urlset><url><loc>https://example.com/a.html</loc></url>\
+            <url><loc><![CDATA[/b.html]]></loc></url></urlset>";
+        assert_eq!(
+            extract(sitemap, &base),
+            vec![
+                Url::parse("https://example.com/a.html").unwrap(),
+                Url::parse("https://example.com/b.html").unwrap()
+            ]
+        );
+        let rss = r#"<rss><channel><item><link>https://example.com/post</link>
+            <enclosure url="https://example.com/ep.mp3" type="audio/mpeg"/></item></channel></rss>"#;
+        assert_eq!(extract(rss, &base).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn depth() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let start = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 1024];
+                let n = socket.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                let body = match request.split_whitespace().nth(1).unwrap() {
+                    "/" => r#"<a href="a.jpg"></a><a href="/p2">next</a>"#,
+                    "/p2" => r#"<a href="b.jpg"></a><a href="/p3">next</a>"#,
+                    _ => r#"<a href="c.jpg"></a>"#,
+                };
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        let filter = LinkFilter {
+            extensions: vec!["jpg".to_string()],
+            pattern: None,
+            depth: 1,
+        };
+        let links = crawl(
+            &Client::new(),
+            Url::parse(&start).unwrap(),
+            &HeaderMap::new(),
+            RedirectPolicy::default(),
+            SpeedLimits::default(),
+            &filter,
+        )
+        .await
+        .unwrap();
+        let paths: Vec<&str> = links.iter().map(Url::path).collect();
+        assert_eq!(paths, vec!["/a.jpg", "/b.jpg"]);
+    }
+
+    #[test]
+    fn filter() {
+        let filter = LinkFilter {
+            extensions: vec!["JPG".to_string()],
+            pattern: Some("/img/".to_string()),
+            depth: 0,
+        }
+        .compile()
+        .unwrap();
+        let accepts = |url| filter.accepts(&Url::parse(url).unwrap());
+        assert!(accepts("https://example.com/img/a.jpg"));
+        assert!(!accepts("https://example.com/img/a.png"));
+        assert!(!accepts("https://example.com/a.jpg"));
+        assert!(LinkFilter {
+            pattern: Some("(".to_string()),
+            ..Default::default()
+        }
+        .compile()
+        .is_err());
+    }
+}
+
+use crate::markup;
+use crate::redirect::{self, RedirectPolicy};
+use crate::transfer::{self, SpeedLimits};
+use crate::Error;
+use regex::Regex;
+use reqwest::header::{HeaderMap, CONTENT_TYPE};
+use reqwest::{Client, StatusCode, Url};
+use std::collections::{HashSet, VecDeque};
+
+// which links found by `Downloader::append_links` become tasks, the others are crawled
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LinkFilter {
+    pub extensions: Vec<String>,
+    pub pattern: Option<String>,
+    pub depth: usize,
+}
+
+impl LinkFilter {
+    pub(crate) fn compile(&self) -> Result<CompiledFilter, Error> {
+        let pattern = match &self.pattern {
+            Some(pattern) => {
+                Some(Regex::new(pattern).map_err(|e| Error::PatternIllegal(e.to_string()))?)
+            }
+            None => None,
+        };
+        Ok(CompiledFilter {
+            extensions: self
+                .extensions
+                .iter()
+                .map(|e| e.trim_start_matches('.').to_ascii_lowercase())
+                .collect(),
+            pattern,
+        })
+    }
+}
+
+pub(crate) struct CompiledFilter {
+    extensions: Vec<String>,
+    pattern: Option<Regex>,
+}
+
+impl CompiledFilter {
+    pub(crate) fn accepts(&self, url: &Url) -> bool {
+        let extension_matches = self.extensions.is_empty()
+            || url
+                .path()
+                .rsplit('/')
+                .next()
+                .and_then(|name| name.rsplit_once('.'))
+                .is_some_and(|(_, ext)| self.extensions.contains(&ext.to_ascii_lowercase()));
+        extension_matches
+            && self
+                .pattern
+                .as_ref()
+                .is_none_or(|pattern| pattern.is_match(url.as_str()))
+    }
+}
+
+// a followed page is only read when it may contain links
+fn is_document(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_none_or(|v| v.contains("html") || v.contains("xml"))
+}
+
+// accepted links of `start` and the pages it leads to, breadth first
+pub(crate) async fn crawl(
+    client: &Client,
+    start: Url,
+    headers: &HeaderMap,
+    policy: RedirectPolicy,
+    speed: SpeedLimits,
+    filter: &LinkFilter,
+) -> Result<Vec<Url>, Error> {
+    let compiled = filter.compile()?;
+    let host = start.host_str().map(str::to_string);
+    let mut visited = HashSet::from([start.clone()]);
+    let mut pages = VecDeque::from([(start, 0)]);
+    let mut found = vec![];
+    let mut accepted = HashSet::new();
+    let mut first = true;
+    while let Some((page, depth)) = pages.pop_front() {
+        let fetched = async {
+            let (url, response) =
+                redirect::fetch(client, page, headers, policy, &mut vec![]).await?;
+            match response.status() {
+                StatusCode::OK => {}
+                StatusCode::NOT_FOUND => return Err(Error::ResourceNotFound),
+                status => return Err(Error::RequestNotOK(status.as_u16())),
+            }
+            if !first && !is_document(response.headers()) {
+                return Ok(None);
+            }
+            let body = transfer::read_body(response, speed).await?;
+            Ok(Some((url, body)))
+        }
+        .await;
+        let (base, body) = match fetched {
+            Ok(Some(fetched)) => fetched,
+            Ok(None) => continue,
+            Err(e) if first => return Err(e),
+            Err(_) => continue,
+        };
+        first = false;
+        for link in extract(&String::from_utf8_lossy(&body), &base) {
+            if compiled.accepts(&link) {
+                if accepted.insert(link.clone()) {
+                    found.push(link);
+                }
+            } else if depth < filter.depth
+                && link.host_str().map(str::to_string) == host
+                && visited.insert(link.clone())
+            {
+                pages.push_back((link, depth + 1));
+            }
+        }
+    }
+    Ok(found)
+}
+
+// attributes holding a single url, on any tag
+const URL_ATTRIBUTES: [&str; 4] = ["href", "src", "data-src", "url"];
+// elements of sitemaps and RSS whose text is a url
+const URL_ELEMENTS: [&str; 2] = ["loc", "link"];
+
+// every http(s) link of an HTML page, sitemap or feed, without fragments
+pub(crate) fn extract(document: &str, base: &Url) -> Vec<Url> {
+    let mut base = base.clone();
+    let mut links = vec![];
+    let mut seen = HashSet::new();
+    let mut push = |base: &Url, link: &str| {
+        if let Ok(mut url) = base.join(link.trim()) {
+            url.set_fragment(None);
+            if matches!(url.scheme(), "http" | "https") && seen.insert(url.clone()) {
+                links.push(url);
+            }
+        }
+    };
+    for tag in markup::tags(document) {
+        if tag.closing {
+            continue;
+        }
+        if tag.name == "base" {
+            if let Some(url) = tag.attribute("href").and_then(|href| base.join(href).ok()) {
+                base = url;
+            }
+            continue;
+        }
+        let mut has_url = false;
+        for (key, value) in &tag.attributes {
+            if URL_ATTRIBUTES.contains(&key.as_str()) {
+                push(&base, value);
+                has_url = true;
+            } else if key == "srcset" {
+                for candidate in value.split(',') {
+                    if let Some(url) = candidate.split_whitespace().next() {
+                        push(&base, url);
+                    }
+                }
+            }
+        }
+        if !has_url && URL_ELEMENTS.contains(&tag.name.as_str()) && !tag.self_closing {
+            push(&base, &tag.text());
+        }
+    }
+    links
+}
@@ -0,0 +1,314 @@
+#[cfg(test)]
+mod tests {
+    use super::ManifestFormat;
+    #[cfg(feature = "hooks-archive")]
+    use crate::Decompress;
+    #[cfg(feature = "archive")]
+    use crate::{ArchiveFormat, ConflictPolicy};
+    use crate::{DownloadTask, Downloader};
+    #[cfg(feature = "hooks-archive")]
+    use base64::Engine;
+    use std::fs;
+    #[cfg(feature = "hooks-archive")]
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn manifests() {
+        let dir = std::env::temp_dir().join(format!("shirodl-manifest-{}", std::process::id()));
+        let mut dler = Downloader::new();
+        dler.set_destination(dir.clone());
+        for format in [
+            ManifestFormat::B3Sums,
+            ManifestFormat::Sha256Sums,
+            ManifestFormat::Jsonl,
+        ] {
+            let name = match format {
+                ManifestFormat::B3Sums => "B3SUMS",
+                ManifestFormat::Sha256Sums => "SHA256SUMS",
+                ManifestFormat::Jsonl => "manifest.jsonl",
+            };
+            assert_eq!(ManifestFormat::from_path(Path::new(name)), Some(format));
+            dler.add_manifest(dir.join(name), format);
+        }
+        for (path, filename) in [("sub", "b.txt"), (".", "a.txt"), (".", "back\\slash.txt")] {
+            dler.append_task(DownloadTask::new(
+                "data:,hello".to_string(),
+                PathBuf::from(path),
+                Some(filename.to_string()),
+            ));
+        }
+        let reports = dler.download_report(|_, _, _, _| {}).unwrap();
+        assert!(reports.iter().all(|r| r.result.is_ok()));
+
+        let sha256 = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+        assert_eq!(
+            fs::read_to_string(dir.join("SHA256SUMS")).unwrap(),
+            format!(
+                "{0}  a.txt\n\\{0}  back\\\\slash.txt\n{0}  sub/b.txt\n",
+                sha256
+            )
+        );
+        let blake3 = blake3::hash(b"hello").to_hex();
+        let b3sums = fs::read_to_string(dir.join("B3SUMS")).unwrap();
+        assert_eq!(b3sums.lines().next().unwrap(), format!("{}  a.txt", blake3));
+        let jsonl = fs::read_to_string(dir.join("manifest.jsonl")).unwrap();
+        let first: serde_json::Value = serde_json::from_str(jsonl.lines().next().unwrap()).unwrap();
+        assert_eq!(first["path"], "a.txt");
+        assert_eq!(first["size"], 5);
+        assert_eq!(first["blake3"], blake3.as_str());
+        assert_eq!(first["sha256"], sha256);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "hooks-archive")]
+    #[test]
+    fn final_files() {
+        let dir =
+            std::env::temp_dir().join(format!("shirodl-manifest-final-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // what auto rename turns `same` into, already holding the content
+        fs::write(dir.join("same.plain"), "hello").unwrap();
+        let mut gzip = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        gzip.write_all(b"hello").unwrap();
+        let gzip = base64::engine::general_purpose::STANDARD.encode(gzip.finish().unwrap());
+        let mut dler = Downloader::new();
+        dler.set_destination(dir.clone());
+        dler.add_manifest(dir.join("B3SUMS"), ManifestFormat::B3Sums);
+        dler.add_hook(Decompress::default());
+        for (url, filename) in [
+            (format!("data:application/gzip;base64,{}", gzip), "a.txt.gz"),
+            ("data:text/plain,hello".to_string(), "same"),
+        ] {
+            dler.append_task(DownloadTask::new(
+                url,
+                PathBuf::new(),
+                Some(filename.to_string()),
+            ));
+        }
+        let reports = dler.download_report(|_, _, _, _| {}).unwrap();
+        assert!(reports.iter().all(|r| r.result.is_ok()));
+        let blake3 = blake3::hash(b"hello").to_hex();
+        assert_eq!(
+            fs::read_to_string(dir.join("B3SUMS")).unwrap(),
+            format!("{0}  a.txt\n{0}  same.plain\n", blake3)
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "archive")]
+    #[test]
+    fn archive_entries() {
+        let dir =
+            std::env::temp_dir().join(format!("shirodl-manifest-archive-{}", std::process::id()));
+        let mut dler = Downloader::new();
+        dler.set_destination(dir.clone());
+        dler.set_archive(dir.join("out.tar"), ArchiveFormat::Tar);
+        dler.set_conflict_policy(ConflictPolicy::KeepBoth);
+        dler.add_manifest(dir.join("B3SUMS"), ManifestFormat::B3Sums);
+        fs::create_dir_all(&dir).unwrap();
+        for content in ["one", "two"] {
+            dler.append_task(DownloadTask::new(
+                format!("data:,{}", content),
+                PathBuf::new(),
+                Some("x.txt".to_string()),
+            ));
+        }
+        let reports = dler.download_report(|_, _, _, _| {}).unwrap();
+        assert!(reports.iter().all(|r| r.result.is_ok()));
+        assert_eq!(
+            fs::read_to_string(dir.join("B3SUMS")).unwrap(),
+            format!(
+                "{}  x (1).txt\n{}  x.txt\n",
+                blake3::hash(b"two").to_hex(),
+                blake3::hash(b"one").to_hex()
+            )
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+use crate::checksum::encode_hex;
+use crate::storage::Storage;
+use crate::verify;
+use crate::Error;
+use sha2::{Digest, Sha256};
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+// layout of a manifest written by `Downloader::add_manifest` once the batch completes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManifestFormat {
+    // `<blake3>  <path>` lines, checked by `b3sum --check`
+    B3Sums,
+    // `<sha256>  <path>` lines, checked by `sha256sum -c`
+    Sha256Sums,
+    // one `{"path", "size", "blake3", "sha256"}` object per line
+    Jsonl,
+}
+
+impl ManifestFormat {
+    // guess from the name: `B3SUMS`, `SHA256SUMS` or a `.jsonl` file
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_ascii_lowercase();
+        if name.starts_with("b3sums") || name.ends_with(".b3") {
+            Some(Self::B3Sums)
+        } else if name.starts_with("sha256sums") || name.ends_with(".sha256") {
+            Some(Self::Sha256Sums)
+        } else if name.ends_with(".jsonl") {
+            Some(Self::Jsonl)
+        } else {
+            None
+        }
+    }
+}
+
+struct Entry {
+    path: String,
+    size: u64,
+    blake3: blake3::Hash,
+    sha256: Option<Vec<u8>>,
+}
+
+// files saved during a batch, written out to every requested manifest at the end
+pub(crate) struct Manifest {
+    root: PathBuf,
+    outputs: Vec<(PathBuf, ManifestFormat)>,
+    entries: Mutex<Vec<Entry>>,
+}
+
+impl Manifest {
+    pub(crate) fn new(root: PathBuf, outputs: Vec<(PathBuf, ManifestFormat)>) -> Self {
+        Self {
+            root,
+            outputs,
+            entries: Mutex::new(vec![]),
+        }
+    }
+
+    // `path` below the destination with `/` separators, `None` outside it
+    fn relative(&self, path: &Path) -> Option<String> {
+        let relative = path.strip_prefix(&self.root).ok()?;
+        let mut parts = vec![];
+        for component in relative.components() {
+            match component {
+                Component::Normal(part) => parts.push(part.to_string_lossy()),
+                Component::CurDir => {}
+                _ => return None,
+            }
+        }
+        Some(parts.join("/"))
+    }
+
+    fn sha256(&self) -> bool {
+        self.outputs
+            .iter()
+            .any(|(_, format)| *format != ManifestFormat::B3Sums)
+    }
+
+    // note that `path` now holds `content`, files outside the destination are left out
+    pub(crate) fn record(&self, path: &Path, content: &[u8]) {
+        if let Some(path) = self.relative(path) {
+            self.record_entry(path, content);
+        }
+    }
+
+    // like `record` for an archive entry, `path` is already relative to the destination
+    pub(crate) fn record_entry(&self, path: String, content: &[u8]) {
+        self.entries.lock().unwrap().push(Entry {
+            path,
+            size: content.len() as u64,
+            blake3: blake3::hash(content),
+            sha256: self.sha256().then(|| Sha256::digest(content).to_vec()),
+        });
+    }
+
+    // like `record`, reading what the hooks left at `path`
+    pub(crate) fn record_stored(&self, storage: &dyn Storage, path: &Path) -> Result<(), Error> {
+        let io_error = |e: std::io::Error| Error::IoError(e.to_string());
+        let files = match storage.metadata(path) {
+            Some(metadata) if metadata.is_dir => verify::files(storage, path).map_err(io_error)?,
+            Some(_) => vec![path.to_path_buf()],
+            // removed by a hook
+            None => return Ok(()),
+        };
+        for file in files {
+            let Some(relative) = self.relative(&file) else {
+                continue;
+            };
+            let mut reader = storage.open(&file).map_err(io_error)?;
+            let (mut size, mut blake3) = (0, blake3::Hasher::new());
+            let mut sha256 = self.sha256().then(Sha256::new);
+            let mut buf = vec![0; 64 * 1024];
+            loop {
+                let n = match reader.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => n,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(io_error(e)),
+                };
+                size += n as u64;
+                blake3.update(&buf[..n]);
+                if let Some(sha256) = &mut sha256 {
+                    sha256.update(&buf[..n]);
+                }
+            }
+            self.entries.lock().unwrap().push(Entry {
+                path: relative,
+                size,
+                blake3: blake3.finalize(),
+                sha256: sha256.map(|sha256| sha256.finalize().to_vec()),
+            });
+        }
+        Ok(())
+    }
+
+    pub(crate) fn finish(&self, storage: &dyn Storage) -> Result<(), Error> {
+        let io_error = |e: std::io::Error| Error::IoError(e.to_string());
+        let mut entries = self.entries.lock().unwrap();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        for (output, format) in &self.outputs {
+            let mut manifest = String::new();
+            for entry in entries.iter() {
+                let sha256 = entry.sha256.as_deref().map(encode_hex).unwrap_or_default();
+                match format {
+                    ManifestFormat::B3Sums => {
+                        manifest += &sums_line(entry.blake3.to_hex().as_str(), &entry.path)
+                    }
+                    ManifestFormat::Sha256Sums => manifest += &sums_line(&sha256, &entry.path),
+                    ManifestFormat::Jsonl => {
+                        let line = serde_json::json!({
+                            "path": entry.path,
+                            "size": entry.size,
+                            "blake3": entry.blake3.to_hex().as_str(),
+                            "sha256": sha256,
+                        });
+                        manifest += &format!("{}\n", line);
+                    }
+                }
+            }
+            if let Some(parent) = output.parent() {
+                storage.create_dir_all(parent).map_err(io_error)?;
+            }
+            storage
+                .create(output)
+                .and_then(|mut file| {
+                    file.write_all(manifest.as_bytes())?;
+                    file.flush()
+                })
+                .map_err(io_error)?;
+        }
+        Ok(())
+    }
+}
+
+// the coreutils format, escaping backslashes and newlines in names
+fn sums_line(digest: &str, path: &str) -> String {
+    if path.contains(['\\', '\n']) {
+        let path = path.replace('\\', "\\\\").replace('\n', "\\n");
+        format!("\\{}  {}\n", digest, path)
+    } else {
+        format!("{}  {}\n", digest, path)
+    }
+}
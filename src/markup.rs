@@ -0,0 +1,179 @@
+#[cfg(test)]
+#[allow(clippy::items_after_test_module)]
+mod tests {
+    use super::tags;
+
+    #[test]
+    fn scan() {
+        let document = r#"<?xml version="1.0"?><!-- <skipped/> --><a HREF='x&amp;y' data-x=1 hidden>
+            <b/><c><![CDATA[a<b]]></c><d> 1 &lt; 2 </d></a>"#;
+        let tags: Vec<_> = tags(document).collect();
+        let names: Vec<_> = tags
+            .iter()
+            .map(|t| format!("{}{}", if t.closing { "/" } else { "" }, t.name))
+            .collect();
+        assert_eq!(names, vec!["a", "b", "c", "/c", "d", "/d", "/a"]);
+        assert_eq!(tags[0].attribute("href"), Some("x&y"));
+        assert_eq!(tags[0].attribute("hidden"), Some(""));
+        assert!(tags[1].self_closing);
+        assert_eq!(tags[2].text(), "a<b");
+        assert_eq!(tags[4].text(), "1 < 2");
+    }
+}
+
+// a minimal scanner for HTML and XML, enough to find links and metadata without a full parser
+
+// a start or end tag, `rest` is the document after it
+pub(crate) struct Tag<'a> {
+    // lowercased
+    pub(crate) name: String,
+    pub(crate) closing: bool,
+    pub(crate) self_closing: bool,
+    // lowercased keys and decoded values
+    pub(crate) attributes: Vec<(String, String)>,
+    rest: &'a str,
+}
+
+impl Tag<'_> {
+    pub(crate) fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    // trimmed text right after the tag, either plain or a CDATA section
+    pub(crate) fn text(&self) -> String {
+        let trimmed = self.rest.trim_start();
+        if let Some(cdata) = trimmed.strip_prefix("<![CDATA[") {
+            return cdata
+                .find("]]>")
+                .map_or(cdata, |end| &cdata[..end])
+                .trim()
+                .to_string();
+        }
+        decode_entities(self.rest[..self.rest.find('<').unwrap_or(self.rest.len())].trim())
+    }
+}
+
+// tags in document order, skipping comments, declarations and processing instructions
+pub(crate) fn tags(document: &str) -> impl Iterator<Item = Tag<'_>> {
+    let mut rest = document;
+    std::iter::from_fn(move || loop {
+        let start = rest.find('<')?;
+        rest = &rest[start..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let end = tag_end(rest);
+        let tag = rest[1..end].strip_suffix('>').unwrap_or(&rest[1..end]);
+        rest = &rest[end..];
+        if tag.starts_with(['!', '?']) {
+            continue;
+        }
+        let (closing, tag) = match tag.strip_prefix('/') {
+            Some(tag) => (true, tag),
+            None => (false, tag),
+        };
+        let name_end = tag
+            .find(|c: char| c.is_whitespace() || c == '/')
+            .unwrap_or(tag.len());
+        return Some(Tag {
+            name: tag[..name_end].to_ascii_lowercase(),
+            closing,
+            self_closing: tag.ends_with('/'),
+            attributes: attributes(&tag[name_end..]),
+            rest,
+        });
+    })
+}
+
+// position after the `>` closing the tag at the start of `rest`, quotes may contain `>`
+fn tag_end(rest: &str) -> usize {
+    let mut quote = None;
+    for (i, c) in rest.char_indices().skip(1) {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            ('>', None) => return i + 1,
+            _ => {}
+        }
+    }
+    rest.len()
+}
+
+// `key=value`, `key='value'`, `key="value"` and bare `key`, keys are lowercased
+fn attributes(tag: &str) -> Vec<(String, String)> {
+    let mut attributes = vec![];
+    let mut rest = tag.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+    while !rest.is_empty() {
+        let key_end = rest
+            .find(|c: char| c.is_whitespace() || c == '=' || c == '/')
+            .unwrap_or(rest.len());
+        let key = rest[..key_end].to_ascii_lowercase();
+        rest = rest[key_end..].trim_start();
+        let mut value = String::new();
+        if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            let (v, remaining) = match after.chars().next() {
+                Some(q @ ('"' | '\'')) => match after[1..].find(q) {
+                    Some(end) => (&after[1..end + 1], &after[end + 2..]),
+                    None => (&after[1..], ""),
+                },
+                _ => {
+                    let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                    (&after[..end], &after[end..])
+                }
+            };
+            value = decode_entities(v);
+            rest = remaining;
+        }
+        if !key.is_empty() {
+            attributes.push((key, value));
+        }
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+    }
+    attributes
+}
+
+pub(crate) fn decode_entities(value: &str) -> String {
+    if !value.contains('&') {
+        return value.to_string();
+    }
+    let mut decoded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let c = match &rest[1..end] {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                numeric => numeric
+                    .strip_prefix("#x")
+                    .or_else(|| numeric.strip_prefix("#X"))
+                    .map(|hex| u32::from_str_radix(hex, 16))
+                    .or_else(|| numeric.strip_prefix('#').map(str::parse))
+                    .and_then(Result::ok)
+                    .and_then(char::from_u32),
+            };
+            c.map(|c| (c, end))
+        });
+        match entity {
+            Some((c, end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
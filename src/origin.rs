@@ -0,0 +1,116 @@
+#[cfg(test)]
+mod tests {
+    use super::{record, sidecar_path, OriginMetadata};
+    use crate::storage::LocalStorage;
+    use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE, ETAG, LAST_MODIFIED};
+    use std::fs;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn sidecar() {
+        let dir = std::env::temp_dir().join(format!("shirodl-origin-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("a.jpg");
+        fs::write(&file, b"jpeg").unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, HeaderValue::from_static("\"v1\""));
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("image/jpeg"));
+        headers.insert(
+            LAST_MODIFIED,
+            HeaderValue::from_static("Sun, 06 Nov 1994 08:49:37 GMT"),
+        );
+        let url = "https://example.com/a.jpg";
+        record(
+            &LocalStorage,
+            &file,
+            url,
+            &headers,
+            true,
+            Some(OriginMetadata::Sidecar),
+        )
+        .unwrap();
+
+        let modified = fs::metadata(&file).unwrap().modified().unwrap();
+        assert_eq!(modified, UNIX_EPOCH + Duration::from_secs(784111777));
+        let sidecar: serde_json::Value =
+            serde_json::from_slice(&fs::read(sidecar_path(&file)).unwrap()).unwrap();
+        assert_eq!(sidecar["url"], url);
+        assert_eq!(sidecar["etag"], "\"v1\"");
+        assert_eq!(sidecar["content_type"], "image/jpeg");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+use crate::storage::Storage;
+use crate::Error;
+use reqwest::header::{HeaderMap, CONTENT_TYPE, ETAG, LAST_MODIFIED};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// where `Downloader::set_origin_metadata` records the url, `ETag` and content type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OriginMetadata {
+    // extended attributes `user.xdg.origin.url`, `user.mime_type` and `user.etag`, written by
+    // `LocalStorage` on unix with the `xattr` feature
+    Xattr,
+    // a `<filename>.meta.json` file next to the downloaded one
+    Sidecar,
+}
+
+pub(crate) const SIDECAR_SUFFIX: &str = ".meta.json";
+
+pub(crate) fn sidecar_path(path: &Path) -> PathBuf {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(SIDECAR_SUFFIX);
+    PathBuf::from(sidecar)
+}
+
+// set the mtime from `Last-Modified` when `mtime` is set, and record where the file came from
+pub(crate) fn record(
+    storage: &dyn Storage,
+    path: &Path,
+    url: &str,
+    headers: &HeaderMap,
+    mtime: bool,
+    metadata: Option<OriginMetadata>,
+) -> Result<(), Error> {
+    let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+    let io_error = |e: std::io::Error| Error::IoError(e.to_string());
+    if mtime {
+        if let Some(modified) =
+            header(LAST_MODIFIED).and_then(|v| httpdate::parse_http_date(v).ok())
+        {
+            storage.set_modified(path, modified).map_err(io_error)?;
+        }
+    }
+    match metadata {
+        Some(OriginMetadata::Xattr) => {
+            let attributes = [
+                ("user.xdg.origin.url", Some(url)),
+                ("user.mime_type", header(CONTENT_TYPE)),
+                ("user.etag", header(ETAG)),
+            ];
+            for (name, value) in attributes {
+                if let Some(value) = value {
+                    storage
+                        .set_attribute(path, name, value.as_bytes())
+                        .map_err(io_error)?;
+                }
+            }
+        }
+        Some(OriginMetadata::Sidecar) => {
+            let sidecar = serde_json::json!({
+                "url": url,
+                "etag": header(ETAG),
+                "content_type": header(CONTENT_TYPE),
+                "last_modified": header(LAST_MODIFIED),
+            });
+            let mut file = storage.create(&sidecar_path(path)).map_err(io_error)?;
+            file.write_all(sidecar.to_string().as_bytes())
+                .and_then(|_| file.flush())
+                .map_err(io_error)?;
+        }
+        None => {}
+    }
+    Ok(())
+}
@@ -0,0 +1,246 @@
+#[cfg(test)]
+mod tests {
+    use super::UrlPattern;
+
+    fn expand(pattern: &str) -> Vec<String> {
+        UrlPattern::parse(pattern).unwrap().iter().collect()
+    }
+
+    #[test]
+    fn ranges_and_lists() {
+        assert_eq!(
+            expand("https://cdn/x/img_[001-003].jpg"),
+            vec![
+                "https://cdn/x/img_001.jpg",
+                "https://cdn/x/img_002.jpg",
+                "https://cdn/x/img_003.jpg"
+            ]
+        );
+        assert_eq!(expand("[0-10:5]"), vec!["0", "5", "10"]);
+        assert_eq!(expand("[a-e:2]"), vec!["a", "c", "e"]);
+        assert_eq!(
+            expand("https://cdn/{a,b}/[1-2].bin"),
+            vec![
+                "https://cdn/a/1.bin",
+                "https://cdn/a/2.bin",
+                "https://cdn/b/1.bin",
+                "https://cdn/b/2.bin"
+            ]
+        );
+        assert_eq!(expand("\\[1-2\\]{x\\,y}"), vec!["[1-2]x,y"]);
+        assert_eq!(UrlPattern::parse("[1-500]{a,b}").unwrap().len(), 1000);
+    }
+
+    #[test]
+    fn illegal() {
+        assert!(UrlPattern::parse("[1-").is_err());
+        assert!(UrlPattern::parse("[5-1]").is_err());
+        assert!(UrlPattern::parse("[a-Z]").is_err());
+        assert!(UrlPattern::parse("[1-5:0]").is_err());
+        assert!(UrlPattern::parse("a}").is_err());
+        assert!(UrlPattern::parse("[0-18446744073709551615]").is_err());
+        assert!(UrlPattern::parse("[0-4294967295][0-4294967295]").is_err());
+    }
+}
+
+use crate::Error;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    List(Vec<String>),
+    Numeric {
+        start: u64,
+        end: u64,
+        step: u64,
+        width: usize,
+    },
+    Alphabetic {
+        start: char,
+        end: char,
+        step: u64,
+    },
+}
+
+impl Segment {
+    // `None` when the count does not fit in `usize`
+    fn len(&self) -> Option<usize> {
+        let count = match self {
+            Segment::Literal(_) => return Some(1),
+            Segment::List(items) => return Some(items.len()),
+            Segment::Numeric {
+                start, end, step, ..
+            } => ((end - start) / step).checked_add(1)?,
+            Segment::Alphabetic { start, end, step } => (*end as u64 - *start as u64) / step + 1,
+        };
+        usize::try_from(count).ok()
+    }
+
+    fn get(&self, index: usize) -> String {
+        match self {
+            Segment::Literal(literal) => literal.clone(),
+            Segment::List(items) => items[index].clone(),
+            Segment::Numeric {
+                start, step, width, ..
+            } => format!("{:0width$}", start + step * index as u64, width = width),
+            Segment::Alphabetic { start, step, .. } => {
+                char::from_u32(*start as u32 + (*step * index as u64) as u32)
+                    .unwrap()
+                    .to_string()
+            }
+        }
+    }
+}
+
+// curl-style url globbing: `[1-100]`, `[001-100:5]`, `[a-z]`, `[a-z:2]` and `{a,b,c}`
+#[derive(Debug, Clone, PartialEq)]
+pub struct UrlPattern {
+    segments: Vec<Segment>,
+    len: usize,
+}
+
+impl UrlPattern {
+    pub fn parse(pattern: &str) -> Result<Self, Error> {
+        let illegal = |reason: &str| Error::PatternIllegal(format!("{}: {}", reason, pattern));
+        let mut segments = vec![];
+        let mut literal = String::new();
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => literal.push(chars.next().ok_or_else(|| illegal("trailing `\\`"))?),
+                '[' | '{' => {
+                    let close = if c == '[' { ']' } else { '}' };
+                    let mut body = String::new();
+                    loop {
+                        match chars.next() {
+                            Some(c) if c == close => break,
+                            Some('\\') => {
+                                body.push('\\');
+                                body.push(chars.next().ok_or_else(|| illegal("trailing `\\`"))?);
+                            }
+                            Some(c) => body.push(c),
+                            None => return Err(illegal(&format!("unclosed `{}`", c))),
+                        }
+                    }
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(if c == '[' {
+                        Self::parse_range(&body).ok_or_else(|| illegal("bad range"))?
+                    } else {
+                        Segment::List(split_list(&body))
+                    });
+                }
+                ']' | '}' => return Err(illegal(&format!("unmatched `{}`", c))),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        let len = segments
+            .iter()
+            .try_fold(1usize, |len, segment| len.checked_mul(segment.len()?))
+            .ok_or_else(|| illegal("too many urls"))?;
+        Ok(Self { segments, len })
+    }
+
+    fn parse_range(body: &str) -> Option<Segment> {
+        let (range, step) = match body.split_once(':') {
+            Some((range, step)) => (range, step.parse().ok().filter(|&s| s > 0)?),
+            None => (body, 1),
+        };
+        let (start, end) = range.split_once('-')?;
+        if let (Ok(s), Ok(e)) = (start.parse::<u64>(), end.parse::<u64>()) {
+            if s > e {
+                return None;
+            }
+            // `[001-100]` pads to the width of the start
+            let width = if start.len() > 1 && start.starts_with('0') {
+                start.len()
+            } else {
+                0
+            };
+            return Some(Segment::Numeric {
+                start: s,
+                end: e,
+                step,
+                width,
+            });
+        }
+        let mut start_chars = start.chars();
+        let mut end_chars = end.chars();
+        match (
+            start_chars.next(),
+            start_chars.next(),
+            end_chars.next(),
+            end_chars.next(),
+        ) {
+            (Some(s), None, Some(e), None)
+                if s <= e
+                    && ((s.is_ascii_lowercase() && e.is_ascii_lowercase())
+                        || (s.is_ascii_uppercase() && e.is_ascii_uppercase())) =>
+            {
+                Some(Segment::Alphabetic {
+                    start: s,
+                    end: e,
+                    step,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    // number of urls the pattern expands to
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // lazily expand the pattern, so huge ranges do not need to fit in memory
+    pub fn iter(&self) -> impl Iterator<Item = String> + '_ {
+        // every length fits, `parse` checked their product
+        let lens: Vec<usize> = self.segments.iter().flat_map(Segment::len).collect();
+        let mut indices = vec![0; self.segments.len()];
+        let mut done = lens.contains(&0);
+        std::iter::from_fn(move || {
+            if done {
+                return None;
+            }
+            let url = self
+                .segments
+                .iter()
+                .zip(&indices)
+                .map(|(segment, &i)| segment.get(i))
+                .collect();
+            // odometer, rightmost first
+            done = true;
+            for (i, len) in indices.iter_mut().zip(&lens).rev() {
+                *i += 1;
+                if *i < *len {
+                    done = false;
+                    break;
+                }
+                *i = 0;
+            }
+            Some(url)
+        })
+    }
+}
+
+// split `a,b\,c` into `a` and `b,c`
+fn split_list(body: &str) -> Vec<String> {
+    let mut items = vec![String::new()];
+    let mut chars = body.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => items.last_mut().unwrap().extend(chars.next()),
+            ',' => items.push(String::new()),
+            c => items.last_mut().unwrap().push(c),
+        }
+    }
+    items
+}
@@ -0,0 +1,206 @@
+#[cfg(test)]
+mod tests {
+    use super::{ProxyPool, ProxyStrategy};
+    use crate::Error;
+    use reqwest::{Client, Proxy};
+    use std::time::Duration;
+
+    fn pool(strategy: ProxyStrategy) -> ProxyPool {
+        let proxies = (1..=3)
+            .map(|i| Proxy::all(format!("http://127.0.0.{}:8080", i)).unwrap())
+            .collect();
+        ProxyPool::new(strategy, proxies, 1, Duration::from_secs(60), |_| {
+            Ok(Client::new())
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn unhealthy_proxy_is_skipped() {
+        let pool = pool(ProxyStrategy::RoundRobin);
+        pool.report::<()>(Some(1), &Err(Error::ProxyError("refused".to_string())));
+        for _ in 0..6 {
+            assert_ne!(pool.pick("example.com", None).0, Some(1));
+        }
+        assert_ne!(pool.pick("example.com", Some(0)).0, Some(0));
+    }
+
+    #[test]
+    fn retry_keeps_the_only_healthy_proxy() {
+        let pool = pool(ProxyStrategy::RoundRobin);
+        for index in [1, 2] {
+            pool.report::<()>(Some(index), &Err(Error::ProxyError("refused".to_string())));
+        }
+        for _ in 0..3 {
+            assert_eq!(pool.pick("example.com", Some(0)).0, Some(0));
+        }
+        pool.report::<()>(Some(0), &Err(Error::ProxyError("refused".to_string())));
+        // all cooling down, the first to recover
+        assert_eq!(pool.pick("example.com", Some(0)).0, Some(1));
+    }
+
+    #[test]
+    fn sticky_per_host() {
+        let pool = pool(ProxyStrategy::StickyPerHost);
+        let first = pool.pick("example.com", None).0;
+        for _ in 0..4 {
+            assert_eq!(pool.pick("example.com", None).0, first);
+        }
+        assert_ne!(pool.pick("example.com", first).0, first);
+    }
+}
+
+use crate::Error;
+use reqwest::{Client, Proxy};
+use std::collections::hash_map::{DefaultHasher, RandomState};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub enum ProxyType {
+    Http,
+    Https,
+    All,
+}
+
+// how tasks are spread across the proxies registered with `Downloader::add_proxy`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyStrategy {
+    // all proxies are registered on one client, reqwest picks them by scheme
+    Combined,
+    // every request takes the next healthy proxy in turn
+    RoundRobin,
+    // every request takes a random healthy proxy
+    Random,
+    // requests to the same host always go through the same healthy proxy
+    StickyPerHost,
+}
+
+struct Health {
+    failures: usize,
+    unhealthy_until: Option<Instant>,
+}
+
+struct PoolEntry {
+    client: Client,
+    health: Mutex<Health>,
+}
+
+pub(crate) struct ProxyPool {
+    strategy: ProxyStrategy,
+    fallback: Option<Client>,
+    entries: Vec<PoolEntry>,
+    next: AtomicUsize,
+    random: RandomState,
+    failure_threshold: usize,
+    cooldown: Duration,
+}
+
+impl ProxyPool {
+    // `build` is called once per proxy, or once with every proxy for the combined client
+    pub(crate) fn new<B>(
+        strategy: ProxyStrategy,
+        proxies: Vec<Proxy>,
+        failure_threshold: usize,
+        cooldown: Duration,
+        build: B,
+    ) -> Result<Self, Error>
+    where
+        B: Fn(Vec<Proxy>) -> Result<Client, Error>,
+    {
+        let (fallback, entries) = if strategy == ProxyStrategy::Combined || proxies.is_empty() {
+            (Some(build(proxies)?), vec![])
+        } else {
+            let entries = proxies
+                .into_iter()
+                .map(|proxy| {
+                    Ok(PoolEntry {
+                        client: build(vec![proxy])?,
+                        health: Mutex::new(Health {
+                            failures: 0,
+                            unhealthy_until: None,
+                        }),
+                    })
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            (None, entries)
+        };
+        Ok(Self {
+            strategy,
+            fallback,
+            entries,
+            next: AtomicUsize::new(0),
+            random: RandomState::new(),
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+        })
+    }
+
+    fn is_healthy(&self, index: usize, now: Instant) -> bool {
+        let health = self.entries[index].health.lock().unwrap();
+        health.unhealthy_until.is_none_or(|until| until <= now)
+    }
+
+    // a client for `host`, avoiding the proxy of the last attempt unless no other is healthy
+    pub(crate) fn pick(&self, host: &str, exclude: Option<usize>) -> (Option<usize>, &Client) {
+        if let Some(client) = &self.fallback {
+            return (None, client);
+        }
+        let now = Instant::now();
+        let count = self.entries.len();
+        let mut candidates: Vec<usize> = (0..count)
+            .filter(|&i| Some(i) != exclude && self.is_healthy(i, now))
+            .collect();
+        if candidates.is_empty() {
+            candidates = exclude
+                .filter(|&i| self.is_healthy(i, now))
+                .into_iter()
+                .collect();
+        }
+        if candidates.is_empty() {
+            // everything is cooling down, use the proxy which recovers first
+            candidates = (0..count).collect();
+            candidates.sort_by_key(|&i| self.entries[i].health.lock().unwrap().unhealthy_until);
+            candidates.truncate(1);
+        }
+        let slot = match self.strategy {
+            ProxyStrategy::Combined | ProxyStrategy::RoundRobin => {
+                self.next.fetch_add(1, Ordering::Relaxed)
+            }
+            ProxyStrategy::Random => {
+                self.random
+                    .hash_one(self.next.fetch_add(1, Ordering::Relaxed)) as usize
+            }
+            ProxyStrategy::StickyPerHost => {
+                let mut hasher = DefaultHasher::new();
+                host.hash(&mut hasher);
+                hasher.finish() as usize
+            }
+        };
+        let index = candidates[slot % candidates.len()];
+        (Some(index), &self.entries[index].client)
+    }
+
+    // record the outcome of a request made through proxy `index`
+    pub(crate) fn report<T>(&self, index: Option<usize>, result: &Result<T, Error>) {
+        let index = match index {
+            Some(index) => index,
+            None => return,
+        };
+        let mut health = self.entries[index].health.lock().unwrap();
+        match result {
+            Err(e) if e.is_proxy_failure() => {
+                health.failures += 1;
+                if health.failures >= self.failure_threshold {
+                    health.unhealthy_until = Some(Instant::now() + self.cooldown);
+                    health.failures = 0;
+                }
+            }
+            _ => {
+                health.failures = 0;
+                health.unhealthy_until = None;
+            }
+        }
+    }
+}
@@ -0,0 +1,196 @@
+#[cfg(test)]
+mod tests {
+    use crate::{DownloadTask, Downloader, Error};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn downloader(dir: &Path) -> Downloader {
+        let mut dler = Downloader::new();
+        dler.set_destination(dir.to_path_buf());
+        dler.set_task_count(1);
+        for name in ["a.txt", "b.txt", "c.txt", "d.txt"] {
+            dler.append_task(DownloadTask::new(
+                "data:,hello".to_string(),
+                PathBuf::new(),
+                Some(name.to_string()),
+            ));
+        }
+        dler
+    }
+
+    #[test]
+    fn quota() {
+        let dir = std::env::temp_dir().join(format!("shirodl-quota-{}", std::process::id()));
+        let mut dler = downloader(&dir);
+        dler.set_quota(Some(12));
+        let called = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&called);
+        let reports = dler
+            .download_report(move |_, _, _, _| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            })
+            .unwrap();
+        assert!(reports[0].result.is_ok() && reports[1].result.is_ok());
+        // the task that went over and the ones never started
+        for report in &reports[2..] {
+            assert!(matches!(report.result, Err(Error::QuotaExceeded(12))));
+        }
+        assert!(dir.join("a.txt").exists() && dir.join("b.txt").exists());
+        assert!(!dir.join("c.txt").exists() && !dir.join("d.txt").exists());
+        assert_eq!(called.load(Ordering::SeqCst), 4);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn streamed() {
+        let dir = std::env::temp_dir().join(format!("shirodl-quota-stream-{}", std::process::id()));
+        let mut dler = Downloader::new();
+        dler.set_destination(dir.clone());
+        dler.set_task_count(1);
+        dler.set_quota(Some(5));
+        let sender = dler.task_channel(1);
+        let producer = std::thread::spawn(move || {
+            for name in ["a.txt", "b.txt", "c.txt", "d.txt", "e.txt"] {
+                let task = DownloadTask::new(
+                    "data:,hello".to_string(),
+                    PathBuf::new(),
+                    Some(name.to_string()),
+                );
+                sender.blocking_send(task).unwrap();
+            }
+        });
+        let reports = dler.download_report(|_, _, _, _| {}).unwrap();
+        producer.join().unwrap();
+        // every task still queued when the batch stopped is reported
+        assert_eq!(reports.len(), 5);
+        assert!(reports[0].result.is_ok());
+        for report in &reports[1..] {
+            assert!(matches!(report.result, Err(Error::QuotaExceeded(5))));
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn free_space() {
+        let dir = std::env::temp_dir().join(format!("shirodl-space-{}", std::process::id()));
+        let mut dler = downloader(&dir);
+        dler.set_min_free_space(Some(u64::MAX / 2));
+        let reports = dler.download_report(|_, _, _, _| {}).unwrap();
+        assert_eq!(reports.len(), 4);
+        assert!(reports
+            .iter()
+            .all(|r| matches!(r.result, Err(Error::InsufficientSpace(..)))));
+        assert!(!dir.join("a.txt").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+use crate::storage::Storage;
+use crate::Error;
+use std::path::Path;
+use std::sync::Mutex;
+
+// why a batch stopped, kept as the errors it turns into do not clone
+#[derive(Debug, Clone, Copy)]
+enum Exhausted {
+    Quota(u64),
+    Space(u64, u64),
+}
+
+impl Exhausted {
+    fn error(self) -> Error {
+        match self {
+            Exhausted::Quota(quota) => Error::QuotaExceeded(quota),
+            Exhausted::Space(needed, available) => Error::InsufficientSpace(needed, available),
+        }
+    }
+}
+
+// bytes a batch may still write, it stops once the quota or the free space runs out
+pub(crate) struct Budget {
+    quota: Option<u64>,
+    min_free_space: Option<u64>,
+    // bytes written so far, and why the batch stopped
+    state: Mutex<(u64, Option<Exhausted>)>,
+}
+
+impl Budget {
+    pub(crate) fn new(quota: Option<u64>, min_free_space: Option<u64>) -> Self {
+        Self {
+            quota,
+            min_free_space,
+            state: Mutex::new((0, None)),
+        }
+    }
+
+    // the error that stopped the batch
+    pub(crate) fn stopped(&self) -> Option<Error> {
+        self.state.lock().unwrap().1.map(Exhausted::error)
+    }
+
+    // fail once the batch stopped
+    pub(crate) fn proceed(&self) -> Result<(), Error> {
+        self.stopped().map_or(Ok(()), Err)
+    }
+
+    // check that `len` more bytes fit, and the free space at `path` when given
+    pub(crate) fn admit(
+        &self,
+        storage: &dyn Storage,
+        path: Option<&Path>,
+        len: u64,
+    ) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        self.check(&mut state, storage, path, len)
+    }
+
+    // like `admit`, and count `len` against the quota as it is about to be written
+    pub(crate) fn consume(
+        &self,
+        storage: &dyn Storage,
+        path: Option<&Path>,
+        len: u64,
+    ) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        self.check(&mut state, storage, path, len)?;
+        state.0 += len;
+        Ok(())
+    }
+
+    fn check(
+        &self,
+        state: &mut (u64, Option<Exhausted>),
+        storage: &dyn Storage,
+        path: Option<&Path>,
+        len: u64,
+    ) -> Result<(), Error> {
+        if let Some(exhausted) = state.1 {
+            return Err(exhausted.error());
+        }
+        let mut exhausted = None;
+        if let Some(quota) = self.quota {
+            if state.0.saturating_add(len) > quota {
+                exhausted = Some(Exhausted::Quota(quota));
+            }
+        }
+        if let (None, Some(min_free_space), Some(path)) = (exhausted, self.min_free_space, path) {
+            let needed = len.saturating_add(min_free_space);
+            match storage.available_space(path) {
+                Some(available) if available < needed => {
+                    exhausted = Some(Exhausted::Space(needed, available));
+                }
+                _ => {}
+            }
+        }
+        match exhausted {
+            Some(exhausted) => {
+                state.1 = Some(exhausted);
+                Err(exhausted.error())
+            }
+            None => Ok(()),
+        }
+    }
+}
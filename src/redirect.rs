@@ -0,0 +1,233 @@
+#[cfg(test)]
+mod tests {
+    use super::{fetch, RedirectPolicy};
+    use crate::Error;
+    use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, COOKIE};
+    use reqwest::{Client, Url};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // answers `/n` with a redirect to `/n-1` and `/0` with 200
+    async fn serve() -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 1024];
+                let n = socket.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                let hop: usize = request[5..].split(' ').next().unwrap().parse().unwrap();
+                let response = if hop == 0 {
+                    "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_string()
+                } else {
+                    format!(
+                        "HTTP/1.1 302 Found\r\nLocation: /{}\r\nContent-Length: 0\r\n\r\n",
+                        hop - 1
+                    )
+                };
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        Url::parse(&base).unwrap()
+    }
+
+    #[tokio::test]
+    async fn records_chain() {
+        let base = serve().await;
+        let client = Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap();
+        let mut chain = vec![];
+        let (url, response) = fetch(
+            &client,
+            base.join("3").unwrap(),
+            &HeaderMap::new(),
+            RedirectPolicy::default(),
+            &mut chain,
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(url, base.join("0").unwrap());
+        assert_eq!(chain.len(), 3);
+
+        let policy = RedirectPolicy {
+            limit: 2,
+            same_host_only: true,
+        };
+        let result = fetch(
+            &client,
+            base.join("3").unwrap(),
+            &HeaderMap::new(),
+            policy,
+            &mut vec![],
+        )
+        .await;
+        assert!(matches!(result, Err(Error::TooManyRedirects(2))));
+    }
+
+    #[tokio::test]
+    async fn strips_credentials() {
+        // answers 403 when it is sent credentials
+        let other = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = format!("http://{}/", other.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut socket, _) = other.accept().await.unwrap();
+            let mut buf = vec![0; 1024];
+            let n = socket.read(&mut buf).await.unwrap();
+            let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+            let status = if request.contains("authorization") || request.contains("cookie") {
+                "403 Forbidden"
+            } else {
+                "200 OK"
+            };
+            let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status);
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 1024];
+            let _request = socket.read(&mut buf).await.unwrap();
+            let response = format!(
+                "HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\n\r\n",
+                target
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+        let client = Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer secret"));
+        headers.insert(COOKIE, HeaderValue::from_static("session=secret"));
+        let (_, response) = fetch(
+            &client,
+            Url::parse(&base).unwrap(),
+            &headers,
+            RedirectPolicy::default(),
+            &mut vec![],
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), 200);
+    }
+
+    #[test]
+    fn strips_downloader_headers() {
+        use crate::{DownloadTask, Downloader};
+        use std::io::{Read, Write};
+        // answers 403 when it is sent credentials
+        let other = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = format!("http://{}/a.bin", other.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut socket, _) = other.accept().unwrap();
+            let mut buf = vec![0; 1024];
+            let n = socket.read(&mut buf).unwrap();
+            let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+            let response = if request.contains("authorization") {
+                "HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\n\r\n"
+            } else {
+                "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok"
+            };
+            socket.write_all(response.as_bytes()).unwrap();
+        });
+        // redirects only requests that carry the credentials
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}/a.bin", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut buf = vec![0; 1024];
+            let n = socket.read(&mut buf).unwrap();
+            let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+            let response = if request.contains("authorization: bearer secret") {
+                format!(
+                    "HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\n\r\n",
+                    target
+                )
+            } else {
+                "HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\n\r\n".to_string()
+            };
+            socket.write_all(response.as_bytes()).unwrap();
+        });
+        let dir = std::env::temp_dir().join(format!("shirodl-redirect-{}", std::process::id()));
+        let mut dler = Downloader::new();
+        dler.set_destination(dir.clone());
+        dler.add_header(AUTHORIZATION, HeaderValue::from_static("Bearer secret"));
+        dler.append_task(DownloadTask::new(base, std::path::PathBuf::new(), None));
+        let reports = dler.download_report(|_, _, _, _| {}).unwrap();
+        assert!(reports[0].result.is_ok());
+        assert_eq!(std::fs::read(dir.join("a.bin")).unwrap(), b"ok");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+use crate::Error;
+use reqwest::header::{HeaderMap, AUTHORIZATION, COOKIE, LOCATION, PROXY_AUTHORIZATION};
+use reqwest::{Client, Response, Url};
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RedirectPolicy {
+    pub(crate) limit: usize,
+    pub(crate) same_host_only: bool,
+}
+
+impl Default for RedirectPolicy {
+    fn default() -> Self {
+        // same limit as reqwest's default policy
+        Self {
+            limit: 10,
+            same_host_only: false,
+        }
+    }
+}
+
+// GET `url` with a client without redirects, recording every hop into `chain`. Credentials
+// in `headers` are dropped once a redirect leaves the origin
+pub(crate) async fn fetch(
+    client: &Client,
+    url: Url,
+    headers: &HeaderMap,
+    policy: RedirectPolicy,
+    chain: &mut Vec<String>,
+) -> Result<(Url, Response), Error> {
+    let mut url = url;
+    let mut headers = headers.clone();
+    loop {
+        let req = client.get(url.clone()).headers(headers.clone()).build()?;
+        let response = client.execute(req).await?;
+        if !response.status().is_redirection() {
+            return Ok((url, response));
+        }
+        let location = match response.headers().get(LOCATION) {
+            Some(location) => location,
+            // nothing to follow, let the caller report the status
+            None => return Ok((url, response)),
+        };
+        let next = location
+            .to_str()
+            .ok()
+            .and_then(|location| url.join(location).ok())
+            .ok_or(Error::UrlIllegal)?;
+        if chain.len() >= policy.limit {
+            return Err(Error::TooManyRedirects(policy.limit));
+        }
+        if policy.same_host_only && next.host_str() != url.host_str() {
+            return Err(Error::RedirectToOtherHost(next.to_string()));
+        }
+        let same_origin = next.scheme() == url.scheme()
+            && next.host_str() == url.host_str()
+            && next.port_or_known_default() == url.port_or_known_default();
+        if !same_origin {
+            for name in [AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION] {
+                headers.remove(name);
+            }
+        }
+        chain.push(next.to_string());
+        url = next;
+    }
+}
@@ -0,0 +1,112 @@
+#[cfg(test)]
+mod tests {
+    use super::TaskSink;
+    use crate::{DownloadTask, Downloader};
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Poll};
+    use tokio::io::AsyncWrite;
+
+    #[derive(Clone, Default)]
+    struct Collector(Arc<Mutex<Vec<u8>>>);
+
+    impl AsyncWrite for Collector {
+        fn poll_write(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn sinks() {
+        let collector = Collector::default();
+        let mut dler = Downloader::new();
+        let mut manifest = DownloadTask::new(
+            "data:application/json,%7B%7D".to_string(),
+            PathBuf::new(),
+            None,
+        );
+        manifest.sink = TaskSink::Memory;
+        dler.append_task(manifest);
+        let mut image = DownloadTask::new(
+            "data:image/png;base64,iVBORw==".to_string(),
+            PathBuf::new(),
+            None,
+        );
+        image.sink = TaskSink::writer(collector.clone());
+        dler.append_task(image);
+        let reports = dler.download_report(|_, _, _, _| {}).unwrap();
+        assert!(reports.iter().all(|r| r.result.is_ok()));
+        assert_eq!(reports[0].content.as_deref(), Some(&b"{}"[..]));
+        assert_eq!(reports[1].content, None);
+        assert_eq!(*collector.0.lock().unwrap(), b"\x89PNG");
+    }
+}
+
+use std::sync::Arc;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::Mutex;
+
+// where the content of a task goes once it is downloaded and checked
+#[derive(Clone, Default)]
+pub enum TaskSink {
+    // the file at `path`/`filename` below the destination
+    #[default]
+    File,
+    // kept in memory and returned in `DownloadReport::content`
+    Memory,
+    // written to the writer in one piece, so a retried download is never written twice
+    Writer(Arc<Mutex<dyn AsyncWrite + Send + Unpin>>),
+}
+
+impl TaskSink {
+    pub fn writer<W: AsyncWrite + Send + Unpin + 'static>(writer: W) -> Self {
+        TaskSink::Writer(Arc::new(Mutex::new(writer)))
+    }
+
+    pub fn is_file(&self) -> bool {
+        matches!(self, TaskSink::File)
+    }
+
+    // hand over `content`, returns it back for `Memory`
+    pub(crate) async fn deliver(&self, content: Vec<u8>) -> std::io::Result<Option<Vec<u8>>> {
+        match self {
+            TaskSink::File => Ok(None),
+            TaskSink::Memory => Ok(Some(content)),
+            TaskSink::Writer(writer) => {
+                let mut writer = writer.lock().await;
+                writer.write_all(&content).await?;
+                writer.flush().await?;
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for TaskSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaskSink::File => write!(f, "File"),
+            TaskSink::Memory => write!(f, "Memory"),
+            TaskSink::Writer(_) => write!(f, "Writer"),
+        }
+    }
+}
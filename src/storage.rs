@@ -0,0 +1,333 @@
+#[cfg(test)]
+mod tests {
+    use super::{Metadata, Storage};
+    use crate::{ConflictPolicy, DownloadTask, Downloader, Error};
+    #[cfg(feature = "hooks-archive")]
+    use crate::{Decompress, ManifestFormat};
+    #[cfg(feature = "hooks-archive")]
+    use base64::Engine;
+    use std::collections::{HashMap, HashSet};
+    use std::io::{self, Read, Write};
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, Mutex};
+    use std::time::SystemTime;
+
+    #[derive(Clone, Default)]
+    struct MemoryStorage {
+        files: Arc<Mutex<HashMap<PathBuf, Vec<u8>>>>,
+        folders: Arc<Mutex<HashSet<PathBuf>>>,
+    }
+
+    struct MemoryFile {
+        path: PathBuf,
+        files: Arc<Mutex<HashMap<PathBuf, Vec<u8>>>>,
+    }
+
+    impl Write for MemoryFile {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let mut files = self.files.lock().unwrap();
+            files.entry(self.path.clone()).or_default().extend(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Storage for MemoryStorage {
+        fn metadata(&self, path: &Path) -> Option<Metadata> {
+            if let Some(content) = self.files.lock().unwrap().get(path) {
+                return Some(Metadata {
+                    is_dir: false,
+                    len: content.len() as u64,
+                    modified: Some(SystemTime::now()),
+                });
+            }
+            self.folders.lock().unwrap().get(path).map(|_| Metadata {
+                is_dir: true,
+                len: 0,
+                modified: None,
+            })
+        }
+
+        fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+            let mut folders = self.folders.lock().unwrap();
+            folders.extend(path.ancestors().map(Path::to_path_buf));
+            Ok(())
+        }
+
+        fn create(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+            self.files
+                .lock()
+                .unwrap()
+                .insert(path.to_path_buf(), vec![]);
+            Ok(Box::new(MemoryFile {
+                path: path.to_path_buf(),
+                files: Arc::clone(&self.files),
+            }))
+        }
+
+        fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+            let files = self.files.lock().unwrap();
+            let content = files.get(path).ok_or(io::ErrorKind::NotFound)?;
+            Ok(Box::new(io::Cursor::new(content.clone())))
+        }
+
+        fn hash(&self, path: &Path) -> io::Result<blake3::Hash> {
+            let files = self.files.lock().unwrap();
+            let content = files.get(path).ok_or(io::ErrorKind::NotFound)?;
+            Ok(blake3::hash(content))
+        }
+
+        fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+            let mut files = self.files.lock().unwrap();
+            let content = files.remove(from).ok_or(io::ErrorKind::NotFound)?;
+            files.insert(to.to_path_buf(), content);
+            Ok(())
+        }
+
+        fn remove(&self, path: &Path) -> io::Result<()> {
+            let mut files = self.files.lock().unwrap();
+            files
+                .remove(path)
+                .map(|_| ())
+                .ok_or(io::ErrorKind::NotFound.into())
+        }
+    }
+
+    #[test]
+    fn memory() {
+        let storage = MemoryStorage::default();
+        let download = |content: &str, conflict| {
+            let mut dler = Downloader::new();
+            dler.set_destination(PathBuf::from("/store"));
+            dler.set_storage(storage.clone());
+            let mut task = DownloadTask::new(
+                format!("data:text/plain,{}", content),
+                PathBuf::from("texts"),
+                Some("a.txt".to_string()),
+            );
+            task.conflict = Some(conflict);
+            dler.append_task(task);
+            dler.download_report(|_, _, _, _| {})
+                .unwrap()
+                .remove(0)
+                .result
+        };
+        assert!(download("one", ConflictPolicy::Skip).is_ok());
+        assert!(matches!(
+            download("two", ConflictPolicy::CompareHash),
+            Err(Error::DifferentFileExisted)
+        ));
+        assert!(download("two", ConflictPolicy::KeepBoth).is_ok());
+        let files = storage.files.lock().unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[Path::new("/store/texts/a.txt")], b"one");
+        assert_eq!(files[Path::new("/store/texts/a (1).txt")], b"two");
+    }
+
+    #[cfg(feature = "hooks-archive")]
+    #[test]
+    fn hooks() {
+        let storage = MemoryStorage::default();
+        let mut gzip = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        gzip.write_all(b"hello").unwrap();
+        let gzip = base64::engine::general_purpose::STANDARD.encode(gzip.finish().unwrap());
+        let mut dler = Downloader::new();
+        dler.set_destination(PathBuf::from("/store"));
+        dler.set_storage(storage.clone());
+        dler.add_hook(Decompress::default());
+        dler.add_manifest(PathBuf::from("/store/B3SUMS"), ManifestFormat::B3Sums);
+        dler.append_task(DownloadTask::new(
+            format!("data:application/gzip;base64,{}", gzip),
+            PathBuf::new(),
+            Some("a.txt.gz".to_string()),
+        ));
+        let reports = dler.download_report(|_, _, _, _| {}).unwrap();
+        assert!(reports[0].result.is_ok());
+        let files = storage.files.lock().unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[Path::new("/store/a.txt")], b"hello");
+        let manifest = format!("{}  a.txt\n", blake3::hash(b"hello").to_hex());
+        assert_eq!(files[Path::new("/store/B3SUMS")], manifest.as_bytes());
+    }
+}
+
+use std::fs;
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// what a `Storage` knows about a stored file or folder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    pub is_dir: bool,
+    pub len: u64,
+    // `None` when the store does not keep modification times
+    pub modified: Option<SystemTime>,
+}
+
+// a reader that can seek, returned by `Storage::open_seekable`
+pub trait ReadSeek: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+// where finished downloads are written, `LocalStorage` unless `Downloader::set_storage` is called
+pub trait Storage: Send + Sync {
+    // `None` when nothing is stored at `path`
+    fn metadata(&self, path: &Path) -> Option<Metadata>;
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+    // open `path` for writing, truncating what is there
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write + Send>>;
+    // open `path` for reading, used by hooks on saved files and `Downloader::verify`
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>>;
+    // blake3 hash of the file at `path`, compared with downloaded content on conflicts
+    fn hash(&self, path: &Path) -> io::Result<blake3::Hash>;
+    // move `from` to `to`, replacing `to` when it exists
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+    fn remove(&self, path: &Path) -> io::Result<()>;
+
+    // open `path` for reading in any order, reads it into memory by default
+    fn open_seekable(&self, path: &Path) -> io::Result<Box<dyn ReadSeek>> {
+        let mut content = vec![];
+        self.open(path)?.read_to_end(&mut content)?;
+        Ok(Box::new(io::Cursor::new(content)))
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.metadata(path).is_some()
+    }
+
+    // whether `path` is a symbolic link, which `Downloader::verify` does not descend into
+    fn is_symlink(&self, _path: &Path) -> bool {
+        false
+    }
+
+    // create an empty file at `path`, failing with `AlreadyExists` when something is there
+    fn create_new(&self, path: &Path) -> io::Result<()> {
+        if self.exists(path) {
+            return Err(io::ErrorKind::AlreadyExists.into());
+        }
+        self.create(path)?.flush()
+    }
+
+    // set the modification time of `path`, ignored by default
+    fn set_modified(&self, _path: &Path, _time: SystemTime) -> io::Result<()> {
+        Ok(())
+    }
+
+    // set the extended attribute `name` of `path`, unsupported by default
+    fn set_attribute(&self, _path: &Path, _name: &str, _value: &[u8]) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    // files and folders directly below `path`, unsupported by default
+    fn list(&self, _path: &Path) -> io::Result<Vec<PathBuf>> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    // free bytes at `path` or the closest folder above it, `None` when unknown
+    fn available_space(&self, _path: &Path) -> Option<u64> {
+        None
+    }
+
+    // put the local file `source` at `path`, copies by default
+    fn link(&self, source: &Path, path: &Path) -> io::Result<()> {
+        let mut file = self.create(path)?;
+        io::copy(&mut fs::File::open(source)?, &mut file)?;
+        file.flush()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LocalStorage;
+
+impl Storage for LocalStorage {
+    fn metadata(&self, path: &Path) -> Option<Metadata> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(Metadata {
+            is_dir: metadata.is_dir(),
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+
+    fn is_symlink(&self, path: &Path) -> bool {
+        fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink())
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        Ok(Box::new(fs::File::create(path)?))
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(fs::File::open(path)?))
+    }
+
+    fn open_seekable(&self, path: &Path) -> io::Result<Box<dyn ReadSeek>> {
+        Ok(Box::new(fs::File::open(path)?))
+    }
+
+    fn create_new(&self, path: &Path) -> io::Result<()> {
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .map(drop)
+    }
+
+    fn hash(&self, path: &Path) -> io::Result<blake3::Hash> {
+        let mut hasher = blake3::Hasher::new();
+        io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+        Ok(hasher.finalize())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn list(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect()
+    }
+
+    fn set_modified(&self, path: &Path, time: SystemTime) -> io::Result<()> {
+        filetime::set_file_mtime(path, filetime::FileTime::from_system_time(time))
+    }
+
+    #[cfg(all(unix, feature = "xattr"))]
+    fn set_attribute(&self, path: &Path, name: &str, value: &[u8]) -> io::Result<()> {
+        xattr::set(path, name, value)
+    }
+
+    #[cfg(unix)]
+    #[allow(clippy::unnecessary_cast)]
+    fn available_space(&self, path: &Path) -> Option<u64> {
+        use std::os::unix::ffi::OsStrExt;
+        let existing = path.ancestors().find(|p| p.exists())?;
+        let existing = std::ffi::CString::new(existing.as_os_str().as_bytes()).ok()?;
+        let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+        if unsafe { libc::statvfs(existing.as_ptr(), &mut stat) } != 0 {
+            return None;
+        }
+        // blocks available to unprivileged users, in fragment size units
+        Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+    }
+
+    // hard link, then reflink and copy across devices
+    fn link(&self, source: &Path, path: &Path) -> io::Result<()> {
+        let _ = fs::remove_file(path);
+        fs::hard_link(source, path)
+            .or_else(|_| reflink_copy::reflink_or_copy(source, path).map(drop))
+    }
+}
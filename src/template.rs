@@ -0,0 +1,313 @@
+#[cfg(test)]
+mod tests {
+    use super::{date, mirror_layout, PathTemplate};
+    use std::path::PathBuf;
+
+    #[test]
+    fn render() {
+        let template =
+            PathTemplate::parse("{host}/{path_dir}/{index:3}_{basename}-{query:size}.{ext}")
+                .unwrap();
+        let (dir, filename) = template
+            .render("https://cdn.example.com/a/b%20c/img.jpg?size=large", 7)
+            .unwrap();
+        assert_eq!(dir, PathBuf::from("cdn.example.com/a/b c"));
+        assert_eq!(filename, "007_img-large.jpg");
+
+        let template = PathTemplate::parse("{path_dir}/{basename}.{ext}").unwrap();
+        let (dir, filename) = template.render("https://example.com/../readme", 0).unwrap();
+        assert_eq!(dir, PathBuf::new());
+        assert_eq!(filename, "readme");
+    }
+
+    #[test]
+    fn illegal() {
+        assert!(PathTemplate::parse("{host").is_err());
+        assert!(PathTemplate::parse("{unknown}").is_err());
+        assert!(PathTemplate::parse("{index:x}").is_err());
+        assert!(PathTemplate::parse("{{literal}}/{hash8}").is_ok());
+    }
+
+    #[test]
+    fn mirror() {
+        let layout = |url| mirror_layout(url).unwrap();
+        assert_eq!(
+            layout("https://example.com/a/b/img.jpg"),
+            (PathBuf::from("example.com/a/b"), "img.jpg".to_string())
+        );
+        assert_eq!(
+            layout("http://example.com:8080/a/"),
+            (
+                PathBuf::from("example.com_8080/a"),
+                "index.html".to_string()
+            )
+        );
+        assert_eq!(
+            layout("https://example.com/img.jpg?w=1/2&h=3.5").1,
+            "img@w=1%2F2&h=3%2E5.jpg"
+        );
+        // name and query must never be confused
+        assert_ne!(
+            layout("https://example.com/img@q.jpg"),
+            layout("https://example.com/img.jpg?q")
+        );
+        assert_ne!(
+            layout("https://example.com/img?q.jpg"),
+            layout("https://example.com/img.jpg?q")
+        );
+    }
+
+    #[test]
+    fn dates() {
+        for (days, expected) in [
+            (-1, "1969-12-31"),
+            (0, "1970-01-01"),
+            (11016, "2000-02-29"),
+            (11017, "2000-03-01"),
+            (19782, "2024-02-29"),
+            (19783, "2024-03-01"),
+            (20147, "2025-02-28"),
+            (20148, "2025-03-01"),
+            (47541, "2100-03-01"),
+        ] {
+            assert_eq!(date(days), expected);
+        }
+    }
+}
+
+use crate::date;
+use crate::Error;
+use percent_encoding::percent_decode_str;
+use reqwest::Url;
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Literal(String),
+    Host,
+    PathDir,
+    Basename,
+    Ext,
+    Date,
+    Index(usize),
+    Query(String),
+    Hash8,
+}
+
+// maps a url to a folder and filename, e.g. `{host}/{path_dir}/{basename}.{ext}`. Also
+// `{date}`, `{index}`, `{index:N}`, `{query:name}` and `{hash8}`, `{{` and `}}` are braces
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathTemplate {
+    parts: Vec<Part>,
+}
+
+impl PathTemplate {
+    pub fn parse(template: &str) -> Result<Self, Error> {
+        let illegal = |reason: &str| Error::TemplateIllegal(format!("{}: {}", reason, template));
+        let mut parts = vec![];
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '}' => return Err(illegal("unmatched `}`")),
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => placeholder.push(c),
+                            None => return Err(illegal("unclosed `{`")),
+                        }
+                    }
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    let (name, arg) = match placeholder.split_once(':') {
+                        Some((name, arg)) => (name, Some(arg)),
+                        None => (placeholder.as_str(), None),
+                    };
+                    parts.push(match (name, arg) {
+                        ("host", None) => Part::Host,
+                        ("path_dir", None) => Part::PathDir,
+                        ("basename", None) => Part::Basename,
+                        ("ext", None) => Part::Ext,
+                        ("date", None) => Part::Date,
+                        ("index", None) => Part::Index(0),
+                        ("index", Some(width)) => Part::Index(
+                            width
+                                .parse()
+                                .map_err(|_| illegal("width of `{index}` is not a number"))?,
+                        ),
+                        ("query", Some(name)) if !name.is_empty() => Part::Query(name.to_string()),
+                        ("hash8", None) => Part::Hash8,
+                        _ => {
+                            return Err(illegal(&format!(
+                                "unknown placeholder `{{{}}}`",
+                                placeholder
+                            )))
+                        }
+                    });
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Self { parts })
+    }
+
+    // render the template for `url`, returning the relative folder and the filename
+    pub fn render(&self, url: &str, index: usize) -> Result<(PathBuf, String), Error> {
+        let url = Url::parse(url).map_err(|_| Error::UrlIllegal)?;
+        let segments: Vec<&str> = url
+            .path_segments()
+            .map(|s| s.filter(|s| !s.is_empty()).collect())
+            .unwrap_or_default();
+        let last = if url.path().ends_with('/') {
+            ""
+        } else {
+            segments.last().copied().unwrap_or("")
+        };
+        let dirs = &segments[..segments.len() - usize::from(!last.is_empty())];
+        let (basename, ext) = match last.rsplit_once('.') {
+            Some((basename, ext)) if !basename.is_empty() => (basename, ext),
+            _ => (last, ""),
+        };
+
+        let mut rendered = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => rendered.push_str(literal),
+                Part::Host => rendered.push_str(&sanitize(url.host_str().unwrap_or(""))),
+                Part::PathDir => rendered.push_str(
+                    &dirs
+                        .iter()
+                        .map(|dir| sanitize(&decode(dir)))
+                        .collect::<Vec<_>>()
+                        .join("/"),
+                ),
+                Part::Basename => rendered.push_str(&sanitize(&decode(basename))),
+                Part::Ext => rendered.push_str(&sanitize(&decode(ext))),
+                Part::Date => rendered.push_str(&today()),
+                Part::Index(width) => {
+                    rendered.push_str(&format!("{:0width$}", index, width = width))
+                }
+                Part::Query(name) => {
+                    let value = url
+                        .query_pairs()
+                        .find(|(k, _)| k == name)
+                        .map(|(_, v)| v.to_string())
+                        .unwrap_or_default();
+                    rendered.push_str(&sanitize(&value));
+                }
+                Part::Hash8 => {
+                    rendered.push_str(&blake3::hash(url.as_str().as_bytes()).to_hex()[..8])
+                }
+            }
+        }
+
+        // empty placeholders must not turn into `..` or absolute paths
+        let mut components: Vec<String> = Path::new(&rendered)
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(c) => Some(c.to_string_lossy().to_string()),
+                _ => None,
+            })
+            .collect();
+        let filename = components
+            .pop()
+            .map(|f| f.trim_end_matches('.').to_string())
+            .filter(|f| !f.is_empty())
+            .ok_or(Error::UrlCannotDownload)?;
+        Ok((components.iter().collect(), filename))
+    }
+}
+
+fn decode(segment: &str) -> String {
+    percent_decode_str(segment).decode_utf8_lossy().to_string()
+}
+
+// values of placeholders must stay inside a single path component
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | '\0' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() / 86400)
+        .unwrap_or(0) as i64;
+    date(days)
+}
+
+// `{date}` of `days` since 1970-01-01
+fn date(days: i64) -> String {
+    let (year, month, day) = date::civil_from_days(days);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// reversible escaping, so distinct inputs always give distinct names
+fn escape(value: &str, extra: &[char]) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if "%/\\:*?\"<>|\0".contains(c) || extra.contains(&c) {
+            escaped.push_str(&format!("%{:02X}", c as u32));
+        } else {
+            escaped.push(c);
+        }
+    }
+    match escaped.as_str() {
+        "." => "%2E".to_string(),
+        ".." => "%2E%2E".to_string(),
+        _ => escaped,
+    }
+}
+
+// `<host>/<url path>` for mirror mode, the query kept as `name@query.ext`
+pub(crate) fn mirror_layout(url: &str) -> Result<(PathBuf, String), Error> {
+    let url = Url::parse(url).map_err(|_| Error::UrlIllegal)?;
+    let host = url.host_str().ok_or(Error::UrlCannotDownload)?;
+    let mut dir = PathBuf::from(match url.port() {
+        Some(port) => format!("{}_{}", escape(host, &['_']), port),
+        None => escape(host, &['_']),
+    });
+    let mut segments: Vec<String> = url
+        .path_segments()
+        .map(|s| s.map(|s| escape(&decode(s), &['@'])).collect())
+        .unwrap_or_default();
+    let last = segments.pop().filter(|last| !last.is_empty());
+    for segment in segments.iter().filter(|s| !s.is_empty()) {
+        dir.push(segment);
+    }
+    let name = last.unwrap_or_else(|| "index.html".to_string());
+    let filename = match url.query() {
+        // `.` is escaped in the query so the extension is always the part after it
+        Some(query) => {
+            let query = escape(query, &['@', '.']);
+            match name.rsplit_once('.') {
+                Some((basename, ext)) if !basename.is_empty() => {
+                    format!("{}@{}.{}", basename, query, ext)
+                }
+                _ => format!("{}@{}", name, query),
+            }
+        }
+        None => name,
+    };
+    Ok((dir, filename))
+}
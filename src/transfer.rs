@@ -0,0 +1,153 @@
+#[cfg(test)]
+mod tests {
+    use super::{read_body, SpeedLimits};
+    use crate::Error;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // sends two bytes of a ten bytes body and then stalls
+    async fn stalling_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 1024];
+            let _ = socket.read(&mut buf).await.unwrap();
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nab")
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn idle_timeout() {
+        let response = reqwest::get(stalling_server().await).await.unwrap();
+        let limits = SpeedLimits {
+            idle: Some(Duration::from_millis(200)),
+            low_speed: None,
+        };
+        let result = read_body(response, limits).await;
+        assert!(matches!(result, Err(Error::ReadIdleTimeout(_))));
+    }
+
+    #[tokio::test]
+    async fn low_speed() {
+        let response = reqwest::get(stalling_server().await).await.unwrap();
+        let limits = SpeedLimits {
+            idle: None,
+            low_speed: Some((100, Duration::from_millis(200))),
+        };
+        let result = read_body(response, limits).await;
+        assert!(matches!(result, Err(Error::LowSpeed(100, _))));
+    }
+}
+
+use crate::Error;
+use reqwest::Response;
+use std::time::{Duration, Instant};
+use tokio::time::timeout;
+
+// never trust content-length for more than this when preallocating
+const MAX_PREALLOCATE: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct SpeedLimits {
+    // longest time allowed between two chunks
+    pub(crate) idle: Option<Duration>,
+    // abort when slower than .0 bytes/sec over .1
+    pub(crate) low_speed: Option<(u64, Duration)>,
+}
+
+// where a body being read stands against the speed limits
+pub(crate) struct Meter {
+    limits: SpeedLimits,
+    last_data: Instant,
+    window_start: Instant,
+    window_bytes: u64,
+}
+
+impl Meter {
+    pub(crate) fn new(limits: SpeedLimits) -> Self {
+        let now = Instant::now();
+        Self {
+            limits,
+            last_data: now,
+            window_start: now,
+            window_bytes: 0,
+        }
+    }
+
+    // how long to wait for the next chunk before a limit is due, so a stall is noticed
+    pub(crate) fn wait(&self) -> Option<Duration> {
+        let now = Instant::now();
+        let idle_wait = self
+            .limits
+            .idle
+            .map(|idle| idle.saturating_sub(now - self.last_data));
+        let speed_wait = self
+            .limits
+            .low_speed
+            .map(|(_, period)| period.saturating_sub(now - self.window_start));
+        match (idle_wait, speed_wait) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    // count a chunk of `len` bytes, `None` when the wait ran out without one
+    pub(crate) fn record(&mut self, len: Option<usize>) -> Result<(), Error> {
+        let now = Instant::now();
+        if let Some(len) = len {
+            self.window_bytes += len as u64;
+            self.last_data = now;
+        }
+        if let Some(idle) = self.limits.idle {
+            if now - self.last_data >= idle {
+                return Err(Error::ReadIdleTimeout(idle));
+            }
+        }
+        if let Some((min_speed, period)) = self.limits.low_speed {
+            let elapsed = now - self.window_start;
+            if elapsed >= period {
+                if (self.window_bytes as f64) < min_speed as f64 * elapsed.as_secs_f64() {
+                    return Err(Error::LowSpeed(min_speed, period));
+                }
+                self.window_start = now;
+                self.window_bytes = 0;
+            }
+        }
+        Ok(())
+    }
+}
+
+// read the whole body, aborting when the transfer stalls or falls below the speed limit
+pub(crate) async fn read_body(
+    mut response: Response,
+    limits: SpeedLimits,
+) -> Result<Vec<u8>, Error> {
+    let capacity = response.content_length().unwrap_or(0) as usize;
+    let mut body = Vec::with_capacity(capacity.min(MAX_PREALLOCATE));
+    let mut meter = Meter::new(limits);
+    loop {
+        // wake up at the next deadline even when no data arrives
+        let chunk = match meter.wait() {
+            Some(wait) => timeout(wait, response.chunk()).await.ok(),
+            None => Some(response.chunk().await),
+        };
+        let len = match chunk {
+            Some(chunk) => match chunk? {
+                Some(chunk) => {
+                    body.extend_from_slice(&chunk);
+                    Some(chunk.len())
+                }
+                None => return Ok(body),
+            },
+            None => None,
+        };
+        meter.record(len)?;
+    }
+}
@@ -0,0 +1,191 @@
+#[cfg(test)]
+#[allow(clippy::items_after_test_module)]
+mod tests {
+    use crate::{Checksum, DownloadTask, Downloader, Error, ManifestFormat};
+    use std::fs;
+    use std::path::PathBuf;
+
+    #[test]
+    fn verify() {
+        let dir = std::env::temp_dir().join(format!("shirodl-verify-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::create_dir_all(dir.join("cache")).unwrap();
+        for (name, content) in [
+            ("a.txt", "aaa"),
+            ("b.txt", "bb"),
+            ("sub/c.txt", "c"),
+            ("d.txt", ""),
+            ("f.png", "png"),
+            ("SHA256SUMS", ""),
+            ("cache/object", ""),
+        ] {
+            fs::write(dir.join(name), content).unwrap();
+        }
+        let mut dler = Downloader::new();
+        dler.set_destination(dir.clone());
+        dler.set_cache(dir.join("cache"));
+        dler.add_manifest(dir.join("SHA256SUMS"), ManifestFormat::Sha256Sums);
+        let task = |name: &str, checksum: Option<&str>, size| {
+            let mut task = DownloadTask::new(
+                format!("https://example.com/{}", name),
+                PathBuf::from("."),
+                None,
+            );
+            task.checksum = checksum.map(|c| Checksum::parse(c).unwrap());
+            task.size = size;
+            task
+        };
+        let sha256_of_aaa = "9834876dcfb05cb167a5c24953eba58c4ac89b1adf57f28f2f9d09af107ee8f0";
+        dler.append_task(task(
+            "a.txt",
+            Some(&format!("sha-256={}", sha256_of_aaa)),
+            Some(3),
+        ));
+        dler.append_task(task("b.txt", None, Some(3)));
+        dler.append_task(task("e.txt", None, None));
+        let mut sub = task(
+            "c.txt",
+            Some(&format!("blake3={}", blake3::hash(b"x"))),
+            None,
+        );
+        sub.path = PathBuf::from("sub");
+        dler.append_task(sub);
+        // saved as `f.png` by auto rename
+        dler.append_task(task("f", None, Some(3)));
+
+        let verification = dler.verify().unwrap();
+        let results: Vec<_> = verification.reports.iter().map(|r| &r.result).collect();
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(Error::SizeMismatch(3, 2))));
+        assert!(matches!(results[2], Err(Error::FileMissing)));
+        assert!(matches!(results[3], Err(Error::ChecksumMismatch(_))));
+        assert!(results[4].is_ok());
+        assert_eq!(verification.extraneous, vec![dir.join("d.txt")]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_loop() {
+        let dir = std::env::temp_dir().join(format!("shirodl-verify-loop-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        std::os::unix::fs::symlink(&dir, dir.join("sub/loop")).unwrap();
+        let mut dler = Downloader::new();
+        dler.set_destination(dir.clone());
+        let verification = dler.verify().unwrap();
+        assert_eq!(verification.extraneous, vec![dir.join("sub/loop")]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+use crate::checksum::{Checksum, HashAlgorithm};
+use crate::conflict;
+use crate::origin;
+use crate::storage::Storage;
+use crate::Error;
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// outcome of checking the file of a single task, returned by `Downloader::verify`
+pub struct VerifyReport {
+    pub url: String,
+    pub path: PathBuf,
+    pub filename: Option<String>,
+    // `FileMissing`, `SizeMismatch` or `ChecksumMismatch` when the file is not as expected
+    pub result: Result<(), Error>,
+}
+
+pub struct Verification {
+    pub reports: Vec<VerifyReport>,
+    // files below the destination that belong to no task
+    pub extraneous: Vec<PathBuf>,
+}
+
+// compare the file at `filepath` with what the task expects, the size first as it is cheap
+pub(crate) fn check(
+    storage: &dyn Storage,
+    filepath: &Path,
+    checksum: &Option<Checksum>,
+    size: Option<u64>,
+) -> Result<(), Error> {
+    let metadata = storage.metadata(filepath).ok_or(Error::FileMissing)?;
+    if metadata.is_dir {
+        return Err(Error::FileExistedAsFolder);
+    }
+    if let Some(size) = size {
+        if metadata.len != size {
+            return Err(Error::SizeMismatch(size, metadata.len));
+        }
+    }
+    let Some(checksum) = checksum else {
+        return Ok(());
+    };
+    let matches = if checksum.algorithm == HashAlgorithm::Blake3 {
+        conflict::hash_file(storage, filepath)?.as_bytes()[..] == checksum.digest[..]
+    } else {
+        storage
+            .open(filepath)
+            .and_then(|mut reader| checksum.algorithm.digest_reader(&mut reader))
+            .map_err(|e| Error::IoError(e.to_string()))?
+            == checksum.digest
+    };
+    if matches {
+        Ok(())
+    } else {
+        Err(Error::ChecksumMismatch(checksum.algorithm))
+    }
+}
+
+// every file below `dir` without following links, none when `storage` cannot list folders
+pub(crate) fn files(storage: &dyn Storage, dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    let mut folders = vec![dir.to_path_buf()];
+    while let Some(folder) = folders.pop() {
+        let entries = match storage.list(&folder) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+        for path in entries {
+            if !storage.is_symlink(&path) && storage.metadata(&path).is_some_and(|m| m.is_dir) {
+                folders.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// the only file next to `filepath` with its name and an extension, as auto rename saves it
+pub(crate) fn renamed(storage: &dyn Storage, filepath: &Path) -> Option<PathBuf> {
+    let name = filepath.file_name()?.to_str()?;
+    if name.contains('.') {
+        return None;
+    }
+    let mut candidates = storage
+        .list(filepath.parent()?)
+        .ok()?
+        .into_iter()
+        .filter(|path| {
+            path.file_stem().and_then(|stem| stem.to_str()) == Some(name)
+                && path.extension().is_some()
+                && storage.metadata(path).is_some_and(|m| !m.is_dir)
+        });
+    match (candidates.next(), candidates.next()) {
+        (Some(renamed), None) => Some(renamed),
+        _ => None,
+    }
+}
+
+// a file is expected, or the sidecar of one
+pub(crate) fn accounted(file: &Path, expected: &HashSet<PathBuf>) -> bool {
+    expected.contains(file)
+        || file
+            .to_str()
+            .and_then(|file| file.strip_suffix(origin::SIDECAR_SUFFIX))
+            .is_some_and(|file| expected.contains(Path::new(file)))
+}